@@ -29,6 +29,12 @@ const FONT_SPECS: &[FontSpec] = &[
         ranges: &[(0x0020, 0x007E), (0x00A0, 0x00FF), (0x0100, 0x017F)],
         is_emoji: false,
     },
+    FontSpec {
+        name: "FONT_SMALL",
+        size: 18.0,
+        ranges: &[(0x0020, 0x007E), (0x00A0, 0x00FF), (0x0100, 0x017F)],
+        is_emoji: false,
+    },
     FontSpec {
         name: "FONT_LARGE",
         size: 64.0,
@@ -47,6 +53,49 @@ const FONT_SPECS: &[FontSpec] = &[
         ],
         is_emoji: true,
     },
+    FontSpec {
+        name: "FONT_EMOJI_SMALL",
+        size: 28.0,
+        ranges: &[(0x2600, 0x26FF), (0x1F300, 0x1F5FF)],
+        is_emoji: true,
+    },
+    // For panels smaller than 800x480
+    FontSpec {
+        name: "COMPACT_HEADER",
+        size: 32.0,
+        ranges: &[(0x0020, 0x007E), (0x00A0, 0x00FF), (0x0100, 0x017F)],
+        is_emoji: false,
+    },
+    FontSpec {
+        name: "COMPACT_BODY",
+        size: 16.0,
+        ranges: &[(0x0020, 0x007E), (0x00A0, 0x00FF), (0x0100, 0x017F)],
+        is_emoji: false,
+    },
+    FontSpec {
+        name: "COMPACT_SMALL",
+        size: 13.0,
+        ranges: &[(0x0020, 0x007E), (0x00A0, 0x00FF), (0x0100, 0x017F)],
+        is_emoji: false,
+    },
+    FontSpec {
+        name: "COMPACT_LARGE",
+        size: 40.0,
+        ranges: &[(0x0020, 0x007E), (0x00A0, 0x00FF), (0x0100, 0x017F)],
+        is_emoji: false,
+    },
+    FontSpec {
+        name: "COMPACT_EMOJI",
+        size: 32.0,
+        ranges: &[(0x2600, 0x26FF), (0x1F300, 0x1F5FF)],
+        is_emoji: true,
+    },
+    FontSpec {
+        name: "COMPACT_EMOJI_SMALL",
+        size: 18.0,
+        ranges: &[(0x2600, 0x26FF), (0x1F300, 0x1F5FF)],
+        is_emoji: true,
+    },
 ];
 
 fn main() {
@@ -189,4 +238,3 @@ pub struct Font {{
         writeln!(file, "}};").unwrap();
     }
 }
-
@@ -3,8 +3,10 @@
 set -x
 
 mkdir -p ~/.config/systemd/user/
-cp ~/calendar-display/calendar-display.service ~/calendar-display/calendar-display-usr1.service ~/calendar-display/calendar-display-usr1.timer ~/.config/systemd/user/
+cp ~/calendar-display/calendar-display.service ~/.config/systemd/user/
 
+# The display schedules its own updates now, so the old timer is redundant
+systemctl --user disable --now calendar-display-usr1.timer 2>/dev/null
+rm -f ~/.config/systemd/user/calendar-display-usr1.service ~/.config/systemd/user/calendar-display-usr1.timer
 systemctl --user daemon-reload
 systemctl --user enable --now calendar-display.service
-systemctl --user enable --now calendar-display-usr1.timer
@@ -0,0 +1,253 @@
+//! The 7.5" v2 panel, driven directly because `epd-waveshare` leaves partial
+//! refresh unimplemented for it. Otherwise the same as its `epd7in5_v2`
+//! driver, partial refresh follows Waveshare's `EPD_7in5_V2.c`.
+
+use super::waveshare::Driver;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use linux_embedded_hal::{Delay, SpidevDevice};
+use rppal::gpio::{InputPin, OutputPin};
+use std::error::Error;
+use std::io::Write;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 480;
+/// Much longer than a full refresh takes, so only a panel that is stuck or not
+/// connected gets here
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+const PANEL_SETTING: u8 = 0x00;
+const POWER_SETTING: u8 = 0x01;
+const POWER_OFF: u8 = 0x02;
+const POWER_ON: u8 = 0x04;
+const BOOSTER_SOFT_START: u8 = 0x06;
+const DEEP_SLEEP: u8 = 0x07;
+const DATA_START_TRANSMISSION_1: u8 = 0x10;
+const DISPLAY_REFRESH: u8 = 0x12;
+const DATA_START_TRANSMISSION_2: u8 = 0x13;
+const DUAL_SPI: u8 = 0x15;
+const PLL_CONTROL: u8 = 0x30;
+const VCOM_AND_DATA_INTERVAL_SETTING: u8 = 0x50;
+const TCON_SETTING: u8 = 0x60;
+const TCON_RESOLUTION: u8 = 0x61;
+const GET_STATUS: u8 = 0x71;
+const PARTIAL_WINDOW: u8 = 0x90;
+const PARTIAL_IN: u8 = 0x91;
+const PARTIAL_OUT: u8 = 0x92;
+const CASCADE_SETTING: u8 = 0xE0;
+const FORCE_TEMPERATURE: u8 = 0xE5;
+
+#[derive(Debug)]
+pub struct BusyTimeout(pub Duration);
+impl std::fmt::Display for BusyTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Display still busy after {:?}", self.0)
+    }
+}
+impl Error for BusyTimeout {}
+
+pub struct Epd7in5V2 {
+    busy: InputPin,
+    dc: OutputPin,
+    rst: OutputPin,
+}
+
+impl Epd7in5V2 {
+    pub fn new(
+        spi: &mut SpidevDevice,
+        busy: InputPin,
+        dc: OutputPin,
+        rst: OutputPin,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut epd = Epd7in5V2 { busy, dc, rst };
+        epd.init(spi)?;
+        Ok(epd)
+    }
+
+    fn init(&mut self, spi: &mut SpidevDevice) -> Result<(), Box<dyn Error>> {
+        self.reset();
+        self.cmd_with_data(spi, BOOSTER_SOFT_START, &[0x17, 0x17, 0x27, 0x17])?;
+        self.cmd_with_data(spi, POWER_SETTING, &[0x07, 0x17, 0x3F, 0x3F])?;
+        self.command(spi, POWER_ON)?;
+        self.wait_until_idle(spi)?;
+        self.cmd_with_data(spi, PANEL_SETTING, &[0x1F])?;
+        self.cmd_with_data(spi, PLL_CONTROL, &[0x06])?;
+        self.cmd_with_data(spi, TCON_RESOLUTION, &[0x03, 0x20, 0x01, 0xE0])?;
+        self.cmd_with_data(spi, DUAL_SPI, &[0x00])?;
+        self.cmd_with_data(spi, TCON_SETTING, &[0x22])?;
+        self.cmd_with_data(spi, VCOM_AND_DATA_INTERVAL_SETTING, &[0x10, 0x07])?;
+        self.wait_until_idle(spi)
+    }
+
+    fn reset(&mut self) {
+        self.rst.set_high();
+        sleep(Duration::from_millis(10));
+        self.rst.set_low();
+        sleep(Duration::from_millis(2));
+        self.rst.set_high();
+        sleep(Duration::from_millis(200));
+    }
+
+    fn command(&mut self, spi: &mut SpidevDevice, command: u8) -> Result<(), Box<dyn Error>> {
+        self.dc.set_low();
+        spi.0.write_all(&[command])?;
+        Ok(())
+    }
+
+    fn cmd_with_data(
+        &mut self,
+        spi: &mut SpidevDevice,
+        command: u8,
+        data: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        self.command(spi, command)?;
+        self.dc.set_high();
+        // spidev refuses transfers over its 4096 byte buffer
+        for chunk in data.chunks(4096) {
+            spi.0.write_all(chunk)?;
+        }
+        Ok(())
+    }
+
+    fn wait_until_idle(&mut self, spi: &mut SpidevDevice) -> Result<(), Box<dyn Error>> {
+        let start = Instant::now();
+        loop {
+            self.command(spi, GET_STATUS)?;
+            sleep(Duration::from_millis(10));
+            if self.busy.is_high() {
+                return Ok(());
+            }
+            if start.elapsed() > BUSY_TIMEOUT {
+                return Err(Box::new(BusyTimeout(BUSY_TIMEOUT)));
+            }
+        }
+    }
+}
+
+/// `area` cut to the panel and widened to whole bytes, `None` if empty
+fn byte_aligned(area: Rectangle) -> Option<Rectangle> {
+    let area = area.intersection(&Rectangle::new(Point::zero(), Size::new(WIDTH, HEIGHT)));
+    if area.is_zero_sized() {
+        return None;
+    }
+    let x = area.top_left.x / 8 * 8;
+    let x_end = (area.top_left.x + area.size.width as i32 + 7) / 8 * 8;
+    Some(Rectangle::new(
+        Point::new(x, area.top_left.y),
+        Size::new((x_end - x) as u32, area.size.height),
+    ))
+}
+
+/// The bytes of `buffer` within a byte aligned `area`, row by row
+fn window(buffer: &[u8], area: Rectangle) -> Vec<u8> {
+    let row = (WIDTH / 8) as usize;
+    let (x, width) = (area.top_left.x as usize / 8, area.size.width as usize / 8);
+    area.rows()
+        .flat_map(|y| {
+            let start = y as usize * row + x;
+            &buffer[start..start + width]
+        })
+        .copied()
+        .collect()
+}
+
+impl Driver for Epd7in5V2 {
+    fn wake_up(&mut self, spi: &mut SpidevDevice, _: &mut Delay) -> Result<(), Box<dyn Error>> {
+        self.init(spi)
+    }
+
+    fn show(
+        &mut self,
+        spi: &mut SpidevDevice,
+        buffer: &[u8],
+        _: &mut Delay,
+    ) -> Result<(), Box<dyn Error>> {
+        self.wait_until_idle(spi)?;
+        self.cmd_with_data(spi, DATA_START_TRANSMISSION_2, buffer)?;
+        self.command(spi, DISPLAY_REFRESH)
+    }
+
+    fn supports_partial(&self) -> bool {
+        true
+    }
+
+    /// The panel loses its memory while powered off, so both the old and new
+    /// contents of the window are sent.
+    fn show_partial(
+        &mut self,
+        spi: &mut SpidevDevice,
+        old: &[u8],
+        new: &[u8],
+        area: Rectangle,
+        _: &mut Delay,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(area) = byte_aligned(area) else {
+            return Ok(());
+        };
+        let (x, y) = (area.top_left.x as u32, area.top_left.y as u32);
+        let (x_end, y_end) = (x + area.size.width, y + area.size.height);
+
+        self.wait_until_idle(spi)?;
+        self.cmd_with_data(spi, CASCADE_SETTING, &[0x02])?;
+        self.cmd_with_data(spi, FORCE_TEMPERATURE, &[0x6E])?;
+        self.cmd_with_data(spi, VCOM_AND_DATA_INTERVAL_SETTING, &[0xA9, 0x07])?;
+        self.command(spi, PARTIAL_IN)?;
+        self.cmd_with_data(
+            spi,
+            PARTIAL_WINDOW,
+            &[
+                (x >> 8) as u8,
+                x as u8,
+                ((x_end - 1) >> 8) as u8,
+                (x_end - 1) as u8,
+                (y >> 8) as u8,
+                y as u8,
+                ((y_end - 1) >> 8) as u8,
+                (y_end - 1) as u8,
+                // Gates scan both inside and outside of the window
+                0x01,
+            ],
+        )?;
+        self.cmd_with_data(spi, DATA_START_TRANSMISSION_1, &window(old, area))?;
+        self.cmd_with_data(spi, DATA_START_TRANSMISSION_2, &window(new, area))?;
+        self.command(spi, DISPLAY_REFRESH)?;
+        self.wait_until_idle(spi)?;
+        self.command(spi, PARTIAL_OUT)
+    }
+
+    fn sleep(&mut self, spi: &mut SpidevDevice, _: &mut Delay) -> Result<(), Box<dyn Error>> {
+        self.wait_until_idle(spi)?;
+        self.command(spi, POWER_OFF)?;
+        self.wait_until_idle(spi)?;
+        self.cmd_with_data(spi, DEEP_SLEEP, &[0xA5])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_are_whole_bytes() {
+        let area = Rectangle::new(Point::new(3, 1), Size::new(10, 2));
+        let area = byte_aligned(area).unwrap();
+        assert_eq!(area, Rectangle::new(Point::new(0, 1), Size::new(16, 2)));
+
+        // Each byte holds its row and column
+        let row = (WIDTH / 8) as usize;
+        let buffer: Vec<u8> = (0..row * HEIGHT as usize)
+            .map(|i| ((i / row) * 16 + i % row) as u8)
+            .collect();
+        assert_eq!(window(&buffer, area), [0x10, 0x11, 0x20, 0x21]);
+
+        let area = byte_aligned(Rectangle::new(Point::new(795, 478), Size::new(20, 20))).unwrap();
+        assert_eq!(area, Rectangle::new(Point::new(792, 478), Size::new(8, 2)));
+        assert_eq!(window(&buffer, area).len(), 2);
+        assert_eq!(
+            byte_aligned(Rectangle::new(Point::new(800, 0), Size::new(8, 8))),
+            None
+        );
+    }
+}
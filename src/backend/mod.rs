@@ -0,0 +1,72 @@
+mod epd7in5_v2;
+mod png_file;
+mod refresh;
+mod waveshare;
+
+use crate::image_gen::Disp;
+use crate::panel::Panel;
+use embedded_graphics::primitives::Rectangle;
+use std::env::{self, VarError};
+use std::error::Error;
+use std::path::PathBuf;
+
+pub use png_file::PngFile;
+pub use refresh::{refresher_from_env, Refresh, Refresher};
+pub use waveshare::Waveshare;
+
+/// Where rendered frames end up.
+pub trait DisplayBackend {
+    /// Gets ready to take a frame, e.g. by powering up and waking the panel
+    fn init(&mut self) -> Result<(), Box<dyn Error>>;
+    fn show(&mut self, frame: &Disp) -> Result<(), Box<dyn Error>>;
+    /// Whether [`DisplayBackend::show_partial`] is quicker than a full
+    /// [`DisplayBackend::show`], worth redrawing the clock every minute for
+    fn supports_partial(&self) -> bool {
+        false
+    }
+    /// Shows `frame`, refreshing only the landscape `area` where the backend
+    /// can. Only valid when nothing outside of `area` changed since the last
+    /// frame shown.
+    fn show_partial(&mut self, frame: &Disp, area: Rectangle) -> Result<(), Box<dyn Error>> {
+        let _ = area;
+        self.show(frame)
+    }
+    /// Powers down until the next [`DisplayBackend::init`]
+    fn sleep(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+#[derive(Debug)]
+pub struct UnknownBackend(pub String);
+impl std::fmt::Display for UnknownBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown display backend `{}`", self.0)
+    }
+}
+impl Error for UnknownBackend {}
+
+pub fn mkbackend(
+    name: &str,
+    panel: Panel,
+    png_path: PathBuf,
+) -> Result<Box<dyn DisplayBackend>, Box<dyn Error>> {
+    match name {
+        "waveshare" => Ok(Box::new(Waveshare::new(panel)?)),
+        "png" => Ok(Box::new(PngFile { path: png_path })),
+        _ => Err(Box::new(UnknownBackend(name.to_string()))),
+    }
+}
+
+/// The backend picked by `DISPLAY_BACKEND`, the Waveshare panel unless set.
+/// `png` writes each frame to `PNG_PATH` instead, so the daemon can run
+/// without a Pi.
+pub fn backend_from_env(panel: Panel) -> Result<Box<dyn DisplayBackend>, Box<dyn Error>> {
+    let name = match env::var("DISPLAY_BACKEND") {
+        Ok(s) => s,
+        Err(VarError::NotPresent) => "waveshare".to_string(),
+        Err(VarError::NotUnicode(_)) => panic!("DISPLAY_BACKEND must be unicode"),
+    };
+    let png_path = env::var_os("PNG_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_PKG_NAME"), ".png")));
+    mkbackend(&name, panel, png_path)
+}
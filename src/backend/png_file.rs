@@ -0,0 +1,52 @@
+use super::DisplayBackend;
+use crate::image_gen::Disp;
+use embedded_graphics::prelude::{OriginDimensions, Point};
+use epd_waveshare::color::TriColor;
+use image::{DynamicImage, Rgb, RgbImage};
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Writes every frame to a PNG file, replacing the last one.
+#[derive(Debug)]
+pub struct PngFile {
+    pub path: PathBuf,
+}
+
+/// The frame as the panel would show it, in landscape. Set bits are black and
+/// the third color red.
+pub fn to_image(frame: &Disp) -> RgbImage {
+    let size = frame.size();
+    RgbImage::from_fn(size.width, size.height, |x, y| {
+        match frame.pixel(Point::new(x as i32, y as i32)) {
+            TriColor::White => Rgb([0, 0, 0]),
+            TriColor::Black => Rgb([255, 255, 255]),
+            TriColor::Chromatic => Rgb([200, 0, 0]),
+        }
+    })
+}
+
+impl DisplayBackend for PngFile {
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn show(&mut self, frame: &Disp) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = self.path.parent().filter(|x| !x.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let image = to_image(frame);
+        if frame.has_color() {
+            image.save(&self.path)?;
+        } else {
+            DynamicImage::ImageRgb8(image)
+                .into_luma8()
+                .save(&self.path)?;
+        }
+        eprintln!("Wrote frame to {}", self.path.display());
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
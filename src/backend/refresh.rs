@@ -0,0 +1,127 @@
+use crate::image_gen::Disp;
+use embedded_graphics::primitives::Rectangle;
+use std::env::{self, VarError};
+
+/// How to get a frame onto the panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refresh {
+    Full,
+    /// Only the given landscape area changed
+    Partial(Rectangle),
+}
+
+/// Decides whether and how to refresh the panel. Partial refreshes leave a
+/// little ghosting behind, so every so often a full one clears it.
+#[derive(Debug)]
+pub struct Refresher {
+    /// Partial refreshes allowed between full ones, none if 0
+    pub full_every: u32,
+    partials: u32,
+    last: Option<Disp>,
+}
+
+impl Refresher {
+    pub fn new(full_every: u32) -> Self {
+        Refresher {
+            full_every,
+            partials: 0,
+            last: None,
+        }
+    }
+
+    /// How to show `frame`, given where its clock is. `None` when nothing
+    /// changed since the last frame, so the panel is left alone. Partial when
+    /// only the clock did and a full refresh isn't due.
+    pub fn plan(&mut self, frame: &Disp, clock: Option<Rectangle>) -> Option<Refresh> {
+        let changed = match &self.last {
+            Some(last) => match frame.diff(last) {
+                Some(changed) => Some(changed),
+                None => return None,
+            },
+            None => None,
+        };
+        self.last = Some(frame.clone());
+        match changed {
+            Some(changed)
+                if self.partials < self.full_every
+                    && clock.is_some_and(|clock| clock.intersection(&changed) == changed) =>
+            {
+                self.partials += 1;
+                Some(Refresh::Partial(changed))
+            }
+            _ => {
+                self.partials = 0;
+                Some(Refresh::Full)
+            }
+        }
+    }
+
+    /// Forgets the last frame, e.g. when it never made it to the panel, so
+    /// the next one is shown in full
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// `FULL_REFRESH_EVERY` partial clock refreshes, 10 unless set. 0 turns
+/// partial refreshes off.
+pub fn refresher_from_env() -> Refresher {
+    let full_every = match env::var("FULL_REFRESH_EVERY") {
+        Ok(s) => s
+            .parse()
+            .expect("FULL_REFRESH_EVERY must be a non-negative integer"),
+        Err(VarError::NotPresent) => 10,
+        Err(VarError::NotUnicode(_)) => panic!("FULL_REFRESH_EVERY must be unicode"),
+    };
+    Refresher::new(full_every)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::panel::Panel;
+    use embedded_graphics::prelude::*;
+    use epd_waveshare::color::TriColor;
+
+    #[test]
+    fn skips_unchanged_and_refreshes_in_full_when_due() {
+        let clock = Rectangle::new(Point::new(10, 10), Size::new(100, 40));
+        let mut frame = Disp::new(Panel::Epd4in2);
+        let tick = |frame: &mut Disp, x| {
+            Pixel(Point::new(x, 20), TriColor::White)
+                .draw(frame)
+                .unwrap();
+            Rectangle::new(Point::new(x, 20), Size::new(1, 1))
+        };
+        let mut refresher = Refresher::new(2);
+        assert_eq!(refresher.plan(&frame, Some(clock)), Some(Refresh::Full));
+        assert_eq!(refresher.plan(&frame, Some(clock)), None);
+        let changed = tick(&mut frame, 20);
+        assert_eq!(
+            refresher.plan(&frame, Some(clock)),
+            Some(Refresh::Partial(changed))
+        );
+        let changed = tick(&mut frame, 30);
+        assert_eq!(
+            refresher.plan(&frame, Some(clock)),
+            Some(Refresh::Partial(changed))
+        );
+        tick(&mut frame, 40);
+        assert_eq!(refresher.plan(&frame, Some(clock)), Some(Refresh::Full));
+        let changed = tick(&mut frame, 50);
+        assert_eq!(
+            refresher.plan(&frame, Some(clock)),
+            Some(Refresh::Partial(changed))
+        );
+
+        tick(&mut frame, 200);
+        assert_eq!(refresher.plan(&frame, Some(clock)), Some(Refresh::Full));
+        tick(&mut frame, 60);
+        assert_eq!(refresher.plan(&frame, None), Some(Refresh::Full));
+
+        let mut refresher = Refresher::new(0);
+        refresher.plan(&frame, Some(clock));
+        tick(&mut frame, 70);
+        assert_eq!(refresher.plan(&frame, Some(clock)), Some(Refresh::Full));
+    }
+}
@@ -0,0 +1,181 @@
+use super::epd7in5_v2::Epd7in5V2;
+use super::DisplayBackend;
+use crate::image_gen::Disp;
+use crate::panel::Panel;
+use embedded_graphics::primitives::Rectangle;
+use epd_waveshare::prelude::WaveshareDisplay;
+use epd_waveshare::{epd2in9_v2, epd4in2, epd5in83_v2, epd7in5, epd7in5_hd, epd7in5b_v2};
+use linux_embedded_hal::{
+    spidev::{self, SpidevOptions},
+    Delay, SpidevDevice,
+};
+use rppal::gpio::{Gpio, InputPin, OutputPin};
+use std::error::Error;
+
+// MOSI (10) and SCLK (11) belong to the spidev driver
+pub const EPD_RST_PIN: u8 = 17;
+pub const EPD_DC_PIN: u8 = 25;
+pub const EPD_CS_PIN: u8 = 8;
+pub const EPD_PWR_PIN: u8 = 18;
+pub const EPD_BUSY_PIN: u8 = 24;
+
+/// The parts of [`WaveshareDisplay`] used here, which unlike it do not
+/// depend on the panel's colors
+pub(super) trait Driver {
+    fn wake_up(&mut self, spi: &mut SpidevDevice, delay: &mut Delay) -> Result<(), Box<dyn Error>>;
+    fn show(
+        &mut self,
+        spi: &mut SpidevDevice,
+        buffer: &[u8],
+        delay: &mut Delay,
+    ) -> Result<(), Box<dyn Error>>;
+    /// Whether [`Driver::show_partial`] does more than a full [`Driver::show`]
+    fn supports_partial(&self) -> bool {
+        false
+    }
+    /// Refreshes only `area` of the panel from `old` to `new`, both whole
+    /// buffers in the panel's native orientation
+    fn show_partial(
+        &mut self,
+        spi: &mut SpidevDevice,
+        old: &[u8],
+        new: &[u8],
+        area: Rectangle,
+        delay: &mut Delay,
+    ) -> Result<(), Box<dyn Error>> {
+        let _ = (old, area);
+        self.show(spi, new, delay)
+    }
+    fn sleep(&mut self, spi: &mut SpidevDevice, delay: &mut Delay) -> Result<(), Box<dyn Error>>;
+}
+
+impl<T: WaveshareDisplay<SpidevDevice, InputPin, OutputPin, OutputPin, Delay>> Driver for T {
+    fn wake_up(&mut self, spi: &mut SpidevDevice, delay: &mut Delay) -> Result<(), Box<dyn Error>> {
+        Ok(WaveshareDisplay::wake_up(self, spi, delay)?)
+    }
+
+    fn show(
+        &mut self,
+        spi: &mut SpidevDevice,
+        buffer: &[u8],
+        delay: &mut Delay,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(self.update_and_display_frame(spi, buffer, delay)?)
+    }
+
+    fn sleep(&mut self, spi: &mut SpidevDevice, delay: &mut Delay) -> Result<(), Box<dyn Error>> {
+        Ok(WaveshareDisplay::sleep(self, spi, delay)?)
+    }
+}
+
+/// A Waveshare panel on `/dev/spidev0.0` of a Raspberry Pi.
+pub struct Waveshare {
+    spi: SpidevDevice,
+    device: Box<dyn Driver>,
+    delay: Delay,
+    cs: OutputPin,
+    pwr: OutputPin,
+    /// What the panel shows, for partial refreshes
+    last: Option<Vec<u8>>,
+}
+
+impl Waveshare {
+    pub fn new(panel: Panel) -> Result<Self, Box<dyn Error>> {
+        let mut spi = SpidevDevice::open("/dev/spidev0.0")?;
+        let options = SpidevOptions::new()
+            .bits_per_word(8)
+            .max_speed_hz(4_000_000)
+            .mode(spidev::SpiModeFlags::SPI_MODE_0)
+            .build();
+        spi.configure(&options)?;
+
+        eprintln!("setting up gpio");
+        let gpio = Gpio::new()?;
+
+        let busy = gpio.get(EPD_BUSY_PIN)?.into_input();
+        let rst = gpio.get(EPD_RST_PIN)?.into_output();
+        let dc = gpio.get(EPD_DC_PIN)?.into_output();
+        let mut cs = gpio.get(EPD_CS_PIN)?.into_output();
+        let mut pwr = gpio.get(EPD_PWR_PIN)?.into_output();
+
+        cs.set_high();
+        pwr.set_high();
+
+        let mut delay = Delay {};
+        let (spi_, delay_) = (&mut spi, &mut delay);
+        let device: Box<dyn Driver> = match panel {
+            Panel::Epd7in5 => Box::new(epd7in5::Epd7in5::new(spi_, busy, dc, rst, delay_, None)?),
+            // Not `epd7in5_v2`, which can't refresh partially
+            Panel::Epd7in5V2 => Box::new(Epd7in5V2::new(spi_, busy, dc, rst)?),
+            Panel::Epd7in5V3 => Box::new(epd7in5b_v2::Epd7in5::new(
+                spi_, busy, dc, rst, delay_, None,
+            )?),
+            Panel::Epd7in5Hd => {
+                Box::new(epd7in5_hd::Epd7in5::new(spi_, busy, dc, rst, delay_, None)?)
+            }
+            Panel::Epd5in83V2 => Box::new(epd5in83_v2::Epd5in83::new(
+                spi_, busy, dc, rst, delay_, None,
+            )?),
+            Panel::Epd4in2 => Box::new(epd4in2::Epd4in2::new(spi_, busy, dc, rst, delay_, None)?),
+            Panel::Epd2in9V2 => {
+                Box::new(epd2in9_v2::Epd2in9::new(spi_, busy, dc, rst, delay_, None)?)
+            }
+        };
+        eprintln!("Created {:?} display", panel);
+
+        cs.set_low();
+        pwr.set_low();
+
+        Ok(Waveshare {
+            spi,
+            device,
+            delay,
+            cs,
+            pwr,
+            last: None,
+        })
+    }
+}
+
+impl DisplayBackend for Waveshare {
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        self.cs.set_high();
+        self.pwr.set_high();
+        self.device.wake_up(&mut self.spi, &mut self.delay)
+    }
+
+    fn show(&mut self, frame: &Disp) -> Result<(), Box<dyn Error>> {
+        let buffer = frame.to_panel();
+        self.last = None;
+        self.device.show(&mut self.spi, &buffer, &mut self.delay)?;
+        self.last = Some(buffer);
+        Ok(())
+    }
+
+    fn supports_partial(&self) -> bool {
+        self.device.supports_partial()
+    }
+
+    fn show_partial(&mut self, frame: &Disp, area: Rectangle) -> Result<(), Box<dyn Error>> {
+        let Some(old) = self.last.take() else {
+            return self.show(frame);
+        };
+        let buffer = frame.to_panel();
+        self.device.show_partial(
+            &mut self.spi,
+            &old,
+            &buffer,
+            frame.to_native(area),
+            &mut self.delay,
+        )?;
+        self.last = Some(buffer);
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), Box<dyn Error>> {
+        let result = self.device.sleep(&mut self.spi, &mut self.delay);
+        self.cs.set_low();
+        self.pwr.set_low();
+        result
+    }
+}
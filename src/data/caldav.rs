@@ -0,0 +1,346 @@
+use crate::data::http::HttpOptions;
+use chrono::{DateTime, Utc};
+use reqwest::{blocking::Client, header::CONTENT_TYPE, Method, Url};
+use std::error::Error;
+
+const DAV: &str = "DAV:";
+const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+
+#[derive(Debug)]
+pub enum CalDavError {
+    NoCalendarHome(String),
+    NoCalendars(String),
+}
+impl std::fmt::Display for CalDavError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoCalendarHome(url) => write!(f, "No calendar home set found from {}", url),
+            Self::NoCalendars(url) => write!(f, "No calendars found under {}", url),
+        }
+    }
+}
+impl std::error::Error for CalDavError {}
+
+/// The parts of a `<d:response>` in a multistatus reply that we care about.
+#[derive(Debug, Default, PartialEq)]
+struct DavResponse {
+    href: String,
+    is_calendar: bool,
+    principal: Option<String>,
+    calendar_home: Option<String>,
+    calendar_data: Option<String>,
+}
+
+fn parse_multistatus(xml: &str) -> Result<Vec<DavResponse>, Box<dyn Error>> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let href_in = |node: roxmltree::Node, ns: &str, name: &str| {
+        node.descendants()
+            .find(|n| n.has_tag_name((ns, name)))?
+            .children()
+            .find(|n| n.has_tag_name((DAV, "href")))?
+            .text()
+            .map(|x| x.trim().to_string())
+    };
+
+    Ok(doc
+        .descendants()
+        .filter(|n| n.has_tag_name((DAV, "response")))
+        .map(|response| DavResponse {
+            href: response
+                .children()
+                .find(|n| n.has_tag_name((DAV, "href")))
+                .and_then(|n| n.text())
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            is_calendar: response
+                .descendants()
+                .filter(|n| n.has_tag_name((DAV, "resourcetype")))
+                .flat_map(|n| n.children())
+                .any(|n| n.has_tag_name((CALDAV, "calendar"))),
+            principal: href_in(response, DAV, "current-user-principal"),
+            calendar_home: href_in(response, CALDAV, "calendar-home-set"),
+            calendar_data: response
+                .descendants()
+                .find(|n| n.has_tag_name((CALDAV, "calendar-data")))
+                .and_then(|n| n.text())
+                .map(|x| x.to_string()),
+        })
+        .collect())
+}
+
+fn request(
+    client: &Client,
+    method: &str,
+    url: &Url,
+    http: &HttpOptions,
+    depth: &str,
+    body: String,
+) -> Result<Vec<DavResponse>, Box<dyn Error>> {
+    let request = client
+        .request(Method::from_bytes(method.as_bytes())?, url.clone())
+        .header("Depth", depth)
+        .header(CONTENT_TYPE, "application/xml; charset=utf-8")
+        .body(body);
+    let text = http.apply(request).send()?.error_for_status()?.text()?;
+    parse_multistatus(&text)
+}
+
+fn propfind(
+    client: &Client,
+    url: &Url,
+    http: &HttpOptions,
+    depth: &str,
+    props: &str,
+) -> Result<Vec<DavResponse>, Box<dyn Error>> {
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="{DAV}" xmlns:c="{CALDAV}"><d:prop>{props}</d:prop></d:propfind>"#
+    );
+    request(client, "PROPFIND", url, http, depth, body)
+}
+
+/// Finds the calendar collections reachable from `url`, which may be a calendar
+/// itself, a principal, a calendar home or any URL reporting the current user
+/// principal (such as the server root).
+fn discover(client: &Client, url: &Url, http: &HttpOptions) -> Result<Vec<Url>, Box<dyn Error>> {
+    let props = "<d:resourcetype/><d:current-user-principal/><c:calendar-home-set/>";
+    let this = propfind(client, url, http, "0", props)?
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    if this.is_calendar {
+        return Ok(vec![url.clone()]);
+    }
+
+    let home = match (this.calendar_home, this.principal) {
+        (Some(home), _) => url.join(&home)?,
+        (None, Some(principal)) => {
+            let principal = url.join(&principal)?;
+            let home = propfind(client, &principal, http, "0", "<c:calendar-home-set/>")?
+                .into_iter()
+                .find_map(|x| x.calendar_home)
+                .ok_or_else(|| CalDavError::NoCalendarHome(principal.to_string()))?;
+            principal.join(&home)?
+        }
+        // Nothing to follow, so this is hopefully the calendar home itself
+        (None, None) => url.clone(),
+    };
+
+    let calendars = propfind(client, &home, http, "1", "<d:resourcetype/>")?
+        .into_iter()
+        .filter(|x| x.is_calendar)
+        .map(|x| home.join(&x.href))
+        .collect::<Result<Vec<_>, _>>()?;
+    if calendars.is_empty() {
+        return Err(Box::new(CalDavError::NoCalendars(home.to_string())));
+    }
+    Ok(calendars)
+}
+
+/// Fetches the iCalendar objects of every calendar reachable from `url` that
+/// have events overlapping `[from, to]`.
+pub fn fetch(
+    url: &str,
+    http: &HttpOptions,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let client = http.client()?;
+    let url = Url::parse(url)?;
+
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<c:calendar-query xmlns:d="{DAV}" xmlns:c="{CALDAV}">
+  <d:prop><c:calendar-data/></d:prop>
+  <c:filter>
+    <c:comp-filter name="VCALENDAR">
+      <c:comp-filter name="VEVENT">
+        <c:time-range start="{}" end="{}"/>
+      </c:comp-filter>
+    </c:comp-filter>
+  </c:filter>
+</c:calendar-query>"#,
+        from.format("%Y%m%dT%H%M%SZ"),
+        to.format("%Y%m%dT%H%M%SZ"),
+    );
+
+    let mut objects = Vec::new();
+    for calendar in discover(&client, &url, http)? {
+        objects.extend(
+            request(&client, "REPORT", &calendar, http, "1", body.clone())?
+                .into_iter()
+                .filter_map(|x| x.calendar_data),
+        );
+    }
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::mock;
+
+    fn wrap_multistatus(responses: &str) -> String {
+        format!(
+            r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">{responses}</d:multistatus>"#
+        )
+    }
+
+    fn response(href: &str, props: &str) -> String {
+        format!(
+            "<d:response><d:href>{href}</d:href><d:propstat><d:prop>{props}</d:prop>\
+             <d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>"
+        )
+    }
+
+    #[test]
+    fn multistatus() {
+        let xml = r#"<?xml version="1.0"?>
+<multistatus xmlns="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+  <response>
+    <href>/dav/user/</href>
+    <propstat>
+      <prop>
+        <resourcetype><collection/></resourcetype>
+        <current-user-principal><href>/dav/principals/user/</href></current-user-principal>
+        <C:calendar-home-set><href>/dav/calendars/user/</href></C:calendar-home-set>
+      </prop>
+      <status>HTTP/1.1 200 OK</status>
+    </propstat>
+  </response>
+  <response>
+    <href>/dav/calendars/user/family/</href>
+    <propstat>
+      <prop>
+        <resourcetype><collection/><C:calendar/></resourcetype>
+        <C:calendar-data>BEGIN:VCALENDAR
+END:VCALENDAR</C:calendar-data>
+      </prop>
+      <status>HTTP/1.1 200 OK</status>
+    </propstat>
+  </response>
+</multistatus>"#;
+
+        assert_eq!(
+            parse_multistatus(xml).unwrap(),
+            [
+                DavResponse {
+                    href: "/dav/user/".to_string(),
+                    is_calendar: false,
+                    principal: Some("/dav/principals/user/".to_string()),
+                    calendar_home: Some("/dav/calendars/user/".to_string()),
+                    calendar_data: None,
+                },
+                DavResponse {
+                    href: "/dav/calendars/user/family/".to_string(),
+                    is_calendar: true,
+                    principal: None,
+                    calendar_home: None,
+                    calendar_data: Some("BEGIN:VCALENDAR\nEND:VCALENDAR".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn discovers_and_queries_calendars() {
+        // XML turns CRLF into LF
+        let ics = "BEGIN:VCALENDAR\nEND:VCALENDAR\n";
+        let (base, requests) = mock::serve_routes(
+            vec![
+                (
+                    "/dav/",
+                    207,
+                    wrap_multistatus(&response(
+                        "/dav/",
+                        "<d:resourcetype><d:collection/></d:resourcetype>\
+                         <d:current-user-principal><d:href>/dav/principals/me/</d:href></d:current-user-principal>",
+                    )),
+                ),
+                (
+                    "/dav/principals/me/",
+                    207,
+                    wrap_multistatus(&response(
+                        "/dav/principals/me/",
+                        "<c:calendar-home-set><d:href>/dav/calendars/me/</d:href></c:calendar-home-set>",
+                    )),
+                ),
+                (
+                    "/dav/calendars/me/",
+                    207,
+                    wrap_multistatus(&format!(
+                        "{}{}{}",
+                        response(
+                            "/dav/calendars/me/",
+                            "<d:resourcetype><d:collection/></d:resourcetype>"
+                        ),
+                        response(
+                            "/dav/calendars/me/family/",
+                            "<d:resourcetype><d:collection/><c:calendar/></d:resourcetype>"
+                        ),
+                        response(
+                            "/dav/calendars/me/inbox/",
+                            "<d:resourcetype><d:collection/><c:schedule-inbox/></d:resourcetype>"
+                        ),
+                    )),
+                ),
+                (
+                    "/dav/calendars/me/family/",
+                    207,
+                    wrap_multistatus(&response(
+                        "/dav/calendars/me/family/event.ics",
+                        &format!("<c:calendar-data>{ics}</c:calendar-data>"),
+                    )),
+                ),
+            ]
+            .into_iter()
+            .map(mock::Route::from)
+            .collect(),
+        );
+
+        let from = "2026-10-17T00:00:00Z".parse().unwrap();
+        let to = "2026-10-31T00:00:00Z".parse().unwrap();
+        let objects = fetch(&format!("{}/dav/", base), &Default::default(), from, to).unwrap();
+        assert_eq!(objects, [ics]);
+
+        let requests = requests.lock().unwrap();
+        let seen: Vec<_> = requests
+            .iter()
+            .map(|x| (x.method.as_str(), x.path.as_str(), x.header("Depth")))
+            .collect();
+        assert_eq!(
+            seen,
+            [
+                ("PROPFIND", "/dav/", Some("0")),
+                ("PROPFIND", "/dav/principals/me/", Some("0")),
+                ("PROPFIND", "/dav/calendars/me/", Some("1")),
+                ("REPORT", "/dav/calendars/me/family/", Some("1")),
+            ]
+        );
+        assert!(requests[3]
+            .body
+            .contains(r#"<c:time-range start="20261017T000000Z" end="20261031T000000Z"/>"#));
+    }
+
+    #[test]
+    fn error_statuses() {
+        let base = mock::serve(vec![("/dav/", 401, String::new())]);
+        let status = |url: String| {
+            fetch(&url, &Default::default(), Utc::now(), Utc::now())
+                .unwrap_err()
+                .downcast::<reqwest::Error>()
+                .unwrap()
+                .status()
+        };
+        assert_eq!(
+            status(format!("{}/dav/", base)),
+            Some(reqwest::StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            status(format!("{}/missing/", base)),
+            Some(reqwest::StatusCode::NOT_FOUND)
+        );
+    }
+}
@@ -1,124 +1,748 @@
-use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Utc};
-use ical::IcalParser;
+use crate::data::caldav;
+use crate::data::datetime::parse_ics_datetime;
+use crate::data::http::HttpOptions;
+use crate::data::recurrence::{Recurrence, RecurrenceRule};
+use crate::data::timezone::{parse_vtimezones, Timezones, Zone};
+use crate::data::FetchStatus;
+use chrono::{DateTime, Days, Local, NaiveDateTime, TimeDelta, Utc};
+use ical::{parser::ical::component::IcalEvent, property::Property, IcalParser};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::error::Error;
 use std::{fs::read_to_string, io::BufReader};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Calendar {
     pub events: Vec<CalendarEvent>,
+    /// When the sources were last loaded, whether or not that worked
     pub time: DateTime<Utc>,
+    /// What was last loaded from each source, in the order of `RunArgs::sources`
+    pub feeds: Vec<Feed>,
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+/// Everything loaded from one source, before filtering and truncation.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Feed {
+    /// `CalendarSource::location` these events were loaded from
+    pub location: String,
+    /// `CalendarSource::label` of the source, to tell it apart in warnings
+    #[serde(default)]
+    pub label: Option<String>,
+    pub events: Vec<CalendarEvent>,
+    /// Recurrences were expanded up to here, so the events can only be reused
+    /// while the horizon stays before it
+    pub window_end: DateTime<Utc>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    #[serde(default)]
+    pub status: FetchStatus,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub struct CalendarEvent {
     pub title: String,
     pub start: DateTime<Local>,
     pub end: Option<DateTime<Local>>,
     pub allday: bool,
+    /// Short marker of the source calendar, shown before the title
+    pub label: Option<String>,
+    pub uid: Option<String>,
+    /// Original start of this instance, for instances of recurring events
+    pub recurrence_id: Option<DateTime<Local>>,
 }
 
-fn load_ics(source: &str) -> Result<String, Box<dyn Error>> {
-    let source = source.replace("webcal://", "https://");
-    if source.starts_with("http://") || source.starts_with("https://") {
-        let response = reqwest::blocking::get(source)?;
-        Ok(response.text()?)
-    } else {
-        Ok(read_to_string(source)?)
+/// What makes two events the same event, possibly seen through two sources.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum EventKey {
+    Uid(String, Option<DateTime<Local>>),
+    Fields(String, DateTime<Local>, Option<DateTime<Local>>),
+}
+
+impl CalendarEvent {
+    fn key(&self) -> EventKey {
+        match &self.uid {
+            Some(uid) => EventKey::Uid(uid.clone(), self.recurrence_id),
+            None => EventKey::Fields(self.title.clone(), self.start, self.end),
+        }
     }
 }
 
-fn parse_ics_datetime(value: &str, allday: &mut bool) -> Option<DateTime<Local>> {
-    // all day event
-    if value.len() == 8 {
-        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
-        *allday = true;
-        return date
-            .and_hms_opt(0, 0, 0)
-            .unwrap()
-            .and_local_timezone(Local)
-            .single();
+/// A file, http(s) or webcal location to read events from, or a CalDAV server
+/// given as `caldav://` (https) or `caldav+http://`.
+#[derive(Debug, Clone)]
+pub struct CalendarSource {
+    pub location: String,
+    pub label: Option<String>,
+    pub http: HttpOptions,
+}
+
+enum IcsResponse {
+    Modified {
+        text: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    NotModified,
+}
+
+/// Reads a file or downloads a feed, asking the server to skip the body when
+/// it has not changed since `previous` was loaded.
+fn load_ics(
+    source: &CalendarSource,
+    previous: Option<&Feed>,
+) -> Result<IcsResponse, Box<dyn Error>> {
+    let location = source.location.replace("webcal://", "https://");
+    if !(location.starts_with("http://") || location.starts_with("https://")) {
+        return Ok(IcsResponse::Modified {
+            text: read_to_string(location)?,
+            etag: None,
+            last_modified: None,
+        });
     }
 
-    // date + time event
-    let fmt = match value.len() {
-        13 => "%Y%m%dT%H%M",
-        15 => "%Y%m%dT%H%M%S",
-        16 => "%Y%m%dT%H%M%SZ",
-        _ => {
-            eprintln!("Don't know how to parse date time string `{}`", value);
-            return None;
-        }
+    let client = source.http.client()?;
+    let mut request = source.http.apply(client.get(location));
+    if let Some(etag) = previous.and_then(|x| x.etag.as_ref()) {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = previous.and_then(|x| x.last_modified.as_ref()) {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let response = request.send()?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(IcsResponse::NotModified);
+    }
+    let response = response.error_for_status()?;
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.to_string())
     };
+    let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+    Ok(IcsResponse::Modified {
+        text: response.text()?,
+        etag,
+        last_modified,
+    })
+}
 
-    let dt = NaiveDateTime::parse_from_str(value, fmt).ok()?;
-    dt.and_local_timezone(Local).single()
+fn param<'a>(prop: &'a Property, name: &str) -> Option<&'a str> {
+    prop.params
+        .as_ref()?
+        .iter()
+        .find(|(key, _)| key == name)
+        .and_then(|(_, values)| values.first())
+        .map(|x| x.as_str())
 }
 
-pub fn mkcalendar(path: &str, max_events: usize) -> Result<Calendar, Box<dyn Error>> {
-    let text = load_ics(path)?;
-    let parser = IcalParser::new(BufReader::new(text.as_bytes()));
+/// A DATE or DATE-TIME value together with the zone it is expressed in.
+struct ZonedTime<'a> {
+    time: NaiveDateTime,
+    zone: Zone<'a>,
+    date_only: bool,
+}
 
-    let today = Local::now().date_naive();
+fn parse_zoned<'a>(prop: &Property, value: &str, zones: &'a Timezones) -> Option<ZonedTime<'a>> {
+    let mut date_only = false;
+    let time = parse_ics_datetime(value, &mut date_only)?;
+    let zone = if date_only {
+        Zone::Floating
+    } else {
+        Zone::resolve(param(prop, "TZID"), value.ends_with('Z'), zones)
+    };
+    Some(ZonedTime {
+        time,
+        zone,
+        date_only,
+    })
+}
 
-    let mut events = parser
-        .filter_map(|x| x.ok())
-        .flat_map(|x| x.events)
-        .filter_map(|event| {
-            let mut title: Option<String> = None;
-            let mut start: Option<DateTime<Local>> = None;
-            let mut end: Option<DateTime<Local>> = None;
-            let mut allday = false;
-
-            for prop in event.properties {
-                match prop.name.as_str() {
-                    "SUMMARY" => title = prop.value,
-                    "DTSTART" => {
-                        if let Some(v) = prop.value {
-                            if let Some(dt) = parse_ics_datetime(&v, &mut allday) {
-                                start = Some(dt);
-                            }
-                        }
-                    }
-
-                    "DTEND" => {
-                        if let Some(v) = prop.value {
-                            if let Some(dt) = parse_ics_datetime(&v, &mut allday) {
-                                end = Some(dt);
-                            }
-                        }
-                    }
-                    _ => {}
+fn property<'a>(event: &'a IcalEvent, name: &str) -> Option<&'a Property> {
+    event.properties.iter().find(|prop| prop.name == name)
+}
+
+/// UID and RECURRENCE-ID of a VEVENT that overrides one instance of a recurring event.
+fn override_of(event: &IcalEvent, zones: &Timezones) -> Option<(String, DateTime<Utc>)> {
+    let uid = property(event, "UID")?.value.clone()?;
+    let prop = property(event, "RECURRENCE-ID")?;
+    let recurrence_id = parse_zoned(prop, prop.value.as_ref()?, zones)?;
+    Some((uid, recurrence_id.zone.to_utc(recurrence_id.time)))
+}
+
+/// Turns one VEVENT into its concrete instances running at some point within
+/// `[from, to]`, leaving out instances listed in `overridden`.
+fn expand_event(
+    event: IcalEvent,
+    zones: &Timezones,
+    overridden: &HashSet<(String, DateTime<Utc>)>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Vec<CalendarEvent> {
+    let override_of = override_of(&event, zones);
+    let mut uid: Option<String> = None;
+    let mut title: Option<String> = None;
+    let mut start: Option<ZonedTime> = None;
+    let mut end: Option<ZonedTime> = None;
+    let mut rule: Option<RecurrenceRule> = None;
+    let mut rdates = Vec::new();
+    let mut exdates = Vec::new();
+
+    for prop in &event.properties {
+        let Some(v) = &prop.value else {
+            continue;
+        };
+        match prop.name.as_str() {
+            "SUMMARY" => title = Some(v.clone()),
+            "UID" => uid = Some(v.clone()),
+            "DTSTART" => {
+                if let Some(dt) = parse_zoned(prop, v, zones) {
+                    start = Some(dt);
+                }
+            }
+            "DTEND" => {
+                if let Some(dt) = parse_zoned(prop, v, zones) {
+                    end = Some(dt);
                 }
             }
+            "RRULE" => match v.parse() {
+                Ok(r) => rule = Some(r),
+                Err(e) => eprintln!("{}", e),
+            },
+            "RDATE" => rdates.extend(v.split(',').filter_map(|x| parse_zoned(prop, x, zones))),
+            "EXDATE" => exdates.extend(v.split(',').filter_map(|x| parse_zoned(prop, x, zones))),
+            _ => {}
+        }
+    }
+
+    let (summary, start) = match (title, start) {
+        (Some(summary), Some(start)) => (summary, start),
+        _ => {
+            return vec![];
+        }
+    };
+    let allday = start.date_only;
+    let zone = start.zone;
 
-            let (summary, start) = match (title, start) {
-                (Some(summary), Some(start)) => (summary, start),
-                _ => {
+    // RDATE and EXDATE may be given in another zone than DTSTART
+    let in_start_zone = |dt: &ZonedTime| zone.wall_time(dt.zone.to_utc(dt.time));
+    let mut recurrence = Recurrence {
+        rule,
+        rdates: rdates.iter().map(in_start_zone).collect(),
+        exdates: exdates
+            .iter()
+            .filter(|x| !x.date_only)
+            .map(in_start_zone)
+            .collect(),
+        exdates_allday: exdates
+            .iter()
+            .filter(|x| x.date_only)
+            .map(|x| x.time.date())
+            .collect(),
+    };
+    if let Some(rule) = recurrence.rule.as_mut().filter(|x| x.until_utc) {
+        rule.until = rule.until.map(|until| zone.wall_time(until.and_utc()));
+    }
+
+    let recurring = recurrence.rule.is_some() || !recurrence.rdates.is_empty();
+    let duration = end
+        .as_ref()
+        .map(|end| end.zone.to_utc(end.time) - zone.to_utc(start.time));
+    // Whole days, which keep all-day events at midnight across DST changes
+    let days = end
+        .as_ref()
+        .filter(|end| allday && end.date_only)
+        .map(|end| end.time - start.time);
+    // Instances that started before `from` may still be running
+    let from = from - duration.unwrap_or_default().max(TimeDelta::zero());
+
+    recurrence
+        .expand(start.time, zone.wall_time(from), zone.wall_time(to))
+        .into_iter()
+        .filter_map(|wall| {
+            let instance = zone.to_utc(wall);
+            let recurrence_id = match &override_of {
+                Some((_, recurrence_id)) => Some(*recurrence_id),
+                None if recurring => Some(instance),
+                None => None,
+            };
+            if let (None, Some(uid), Some(recurrence_id)) = (&override_of, &uid, recurrence_id) {
+                if overridden.contains(&(uid.clone(), recurrence_id)) {
                     return None;
                 }
-            };
-
-            if start.date_naive() < today {
-                return None;
             }
-
+            let end = match days {
+                Some(days) => Some(zone.to_utc(wall + days)),
+                None => duration.map(|duration| instance + duration),
+            };
             Some(CalendarEvent {
-                title: summary,
-                start,
-                end,
+                title: summary.clone(),
+                start: instance.with_timezone(&Local),
+                end: end.map(|x| x.with_timezone(&Local)),
                 allday,
+                label: None,
+                uid: uid.clone(),
+                recurrence_id: recurrence_id.map(|x| x.with_timezone(&Local)),
             })
         })
-        .collect::<Vec<CalendarEvent>>();
+        .collect()
+}
 
-    events.dedup();
+/// Every event of an ICS document running at some point within `[from, to]`
+fn parse_ics(text: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<CalendarEvent> {
+    IcalParser::new(BufReader::new(text.as_bytes()))
+        .filter_map(|x| x.ok())
+        .flat_map(|calendar| {
+            let zones = parse_vtimezones(calendar.timezones);
+            let overridden = calendar
+                .events
+                .iter()
+                .filter_map(|event| override_of(event, &zones))
+                .collect();
+            calendar
+                .events
+                .into_iter()
+                .flat_map(|event| expand_event(event, &zones, &overridden, from, to))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn load_source(
+    source: &CalendarSource,
+    previous: Option<&Feed>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Feed, Box<dyn Error>> {
+    // Expand a day further than needed so an unchanged feed stays usable for a while
+    let window_end = to + Days::new(1);
+    let previous = previous.filter(|feed| to <= feed.window_end);
+
+    let (texts, etag, last_modified) = if let Some(rest) = source.location.strip_prefix("caldav://")
+    {
+        let url = format!("https://{}", rest);
+        (
+            caldav::fetch(&url, &source.http, from, window_end)?,
+            None,
+            None,
+        )
+    } else if let Some(rest) = source.location.strip_prefix("caldav+http://") {
+        let url = format!("http://{}", rest);
+        (
+            caldav::fetch(&url, &source.http, from, window_end)?,
+            None,
+            None,
+        )
+    } else {
+        match load_ics(source, previous)? {
+            IcsResponse::Modified {
+                text,
+                etag,
+                last_modified,
+            } => (vec![text], etag, last_modified),
+            IcsResponse::NotModified => match previous {
+                Some(previous) => return Ok(previous.clone()),
+                None => return Err("Got 304 Not Modified for an unconditional request".into()),
+            },
+        }
+    };
+
+    let events = texts
+        .iter()
+        .flat_map(|text| parse_ics(text, from, window_end))
+        .map(|event| CalendarEvent {
+            label: source.label.clone(),
+            ..event
+        })
+        .collect();
+
+    Ok(Feed {
+        location: source.location.clone(),
+        label: source.label.clone(),
+        events,
+        window_end,
+        etag,
+        last_modified,
+        status: previous.map(|x| x.status.clone()).unwrap_or_default(),
+    })
+}
+
+/// Loads every source and merges their events into one calendar.
+///
+/// A source that fails keeps the events it had in `previous`, and its
+/// [`Feed::status`] records the failure.
+pub fn mkcalendar(
+    sources: &[CalendarSource],
+    previous: &Calendar,
+    max_events: usize,
+    horizon_days: u64,
+) -> Result<Calendar, Box<dyn Error>> {
+    let now = Local::now();
+    let today = now.date_naive();
+    let from = today
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_local_timezone(Local)
+        .earliest()
+        .ok_or("Local midnight does not exist")?
+        .to_utc();
+    let to = now.to_utc() + Days::new(horizon_days);
+
+    let mut feeds = Vec::new();
+    for (index, source) in sources.iter().enumerate() {
+        let previous = previous
+            .feeds
+            .get(index)
+            .filter(|feed| feed.location == source.location);
+        let feed = match load_source(source, previous, from, to) {
+            Ok(mut feed) => {
+                feed.status.succeeded(Utc::now());
+                feed
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch calendar `{}`: {}", source.location, e);
+                let mut feed = previous.cloned().unwrap_or_else(|| Feed {
+                    location: source.location.clone(),
+                    ..Default::default()
+                });
+                feed.label = source.label.clone();
+                feed.status.failed(e.as_ref());
+                feed
+            }
+        };
+        feeds.push(feed);
+    }
+
+    let mut events = feeds
+        .iter()
+        .flat_map(|feed| feed.events.iter().cloned())
+        .collect::<Vec<_>>();
+    // Keep everything that has not ended yet, including multi-day events
+    // that started on an earlier day
+    events.retain(|event| match event.end {
+        Some(end) => end > now,
+        None => event.start.date_naive() >= today,
+    });
+    events.retain(|event| event.start <= to);
+    // Chronological, with all-day events first within their day
+    events.sort_by_key(|event| (event.start.date_naive(), !event.allday, event.start));
+    let mut seen = HashSet::new();
+    events.retain(|event| seen.insert(event.key()));
+    events.truncate(max_events);
 
     Ok(Calendar {
         time: Utc::now(),
-        events: if max_events < events.len() {
-            events[0..max_events].to_vec()
-        } else {
-            events
-        },
+        events,
+        feeds,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::http::Auth;
+    use crate::data::mock;
+    use chrono::{NaiveDate, TimeZone};
+    use std::path::PathBuf;
+
+    fn local_midnight(y: i32, m: u32, d: u32) -> DateTime<Local> {
+        Local
+            .from_local_datetime(&NaiveDate::from_ymd_opt(y, m, d).unwrap().into())
+            .earliest()
+            .unwrap()
+    }
+
+    fn ics(events: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{events}END:VCALENDAR\r\n")
+    }
+
+    fn vevent(uid: &str, title: &str, start: &str, extra: &str) -> String {
+        format!("BEGIN:VEVENT\r\nUID:{uid}\r\nSUMMARY:{title}\r\nDTSTART{start}\r\n{extra}END:VEVENT\r\n")
+    }
+
+    /// `:<date>T<time>` in floating local time, `days` after today
+    fn local(days: u64, time: &str) -> String {
+        let date = Local::now().date_naive() + Days::new(days);
+        format!(":{}T{}", date.format("%Y%m%d"), time)
+    }
+
+    /// Writes an ICS file for a test, named after it so tests don't collide
+    fn source(name: &str, label: &str, text: &str) -> CalendarSource {
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("calendar-{}-{}.ics", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        CalendarSource {
+            location: path.to_str().unwrap().to_string(),
+            label: Some(label.to_string()),
+            http: Default::default(),
+        }
+    }
+
+    fn titles(calendar: &Calendar) -> Vec<(&str, &str)> {
+        calendar
+            .events
+            .iter()
+            .map(|x| (x.label.as_deref().unwrap_or(""), x.title.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn all_day_events_end_at_midnight_across_dst() {
+        // Both the US and the EU change clocks within these days
+        let text = ics("BEGIN:VEVENT\r\n\
+             UID:trip\r\n\
+             SUMMARY:Trip\r\n\
+             DTSTART;VALUE=DATE:20260307\r\n\
+             DTEND;VALUE=DATE:20260331\r\n\
+             RRULE:FREQ=YEARLY;COUNT=2\r\n\
+             END:VEVENT\r\n");
+        let from = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2028, 1, 1, 0, 0, 0).unwrap();
+        let events = parse_ics(&text, from, to);
+        let spans: Vec<_> = events.iter().map(|x| (x.start, x.end)).collect();
+        assert_eq!(
+            spans,
+            vec![
+                (
+                    local_midnight(2026, 3, 7),
+                    Some(local_midnight(2026, 3, 31))
+                ),
+                (
+                    local_midnight(2027, 3, 7),
+                    Some(local_midnight(2027, 3, 31))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn recurrences_keep_instances_skipped_by_dst() {
+        let text = ics("BEGIN:VEVENT\r\n\
+             UID:night\r\n\
+             SUMMARY:Night shift\r\n\
+             DTSTART;TZID=America/New_York:20260307T023000\r\n\
+             DTEND;TZID=America/New_York:20260307T033000\r\n\
+             RRULE:FREQ=DAILY;COUNT=3\r\n\
+             END:VEVENT\r\n");
+        let from = Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2026, 4, 1, 0, 0, 0).unwrap();
+        let starts: Vec<_> = parse_ics(&text, from, to)
+            .iter()
+            .map(|x| x.start.to_utc())
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                Utc.with_ymd_and_hms(2026, 3, 7, 7, 30, 0).unwrap(),
+                // 02:30 doesn't exist on the 8th, so it is taken in EST
+                Utc.with_ymd_and_hms(2026, 3, 8, 7, 30, 0).unwrap(),
+                Utc.with_ymd_and_hms(2026, 3, 9, 6, 30, 0).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn merged_sources_sorted_deduped_then_truncated() {
+        let standup = format!(
+            "{}{}",
+            vevent(
+                "standup",
+                "Standup",
+                &local(1, "090000"),
+                "RRULE:FREQ=DAILY;COUNT=3\r\n"
+            ),
+            vevent(
+                "standup",
+                "Standup (moved)",
+                &local(2, "110000"),
+                &format!("RECURRENCE-ID{}\r\n", local(2, "090000")),
+            ),
+        );
+        let a = source(
+            "sort-a",
+            "A",
+            &ics(&format!(
+                "{}{}",
+                vevent("late", "Late", &local(1, "150000"), ""),
+                standup
+            )),
+        );
+        // Out of order, with the last event first so it only goes if
+        // truncation happens after sorting
+        let b = source(
+            "sort-b",
+            "B",
+            &ics(&format!(
+                "{}{}{}{}",
+                vevent("far", "Far", &local(10, "080000"), ""),
+                vevent("early", "Early", &local(3, "080000"), ""),
+                vevent("late", "Late", &local(1, "150000"), ""),
+                vevent(
+                    "holiday",
+                    "Holiday",
+                    &format!(
+                        ";VALUE=DATE:{}",
+                        (Local::now().date_naive() + Days::new(1)).format("%Y%m%d")
+                    ),
+                    "",
+                ),
+            )),
+        );
+
+        let calendar = mkcalendar(&[a, b], &Calendar::default(), 6, 30).unwrap();
+        assert_eq!(
+            titles(&calendar),
+            vec![
+                // All-day first within the day, and `Late` only once, from the
+                // first source
+                ("B", "Holiday"),
+                ("A", "Standup"),
+                ("A", "Late"),
+                // The override replaces its instance
+                ("A", "Standup (moved)"),
+                ("B", "Early"),
+                ("A", "Standup"),
+            ]
+        );
+        assert!(calendar.events.iter().all(|x| x.title != "Far"));
+    }
+
+    #[test]
+    fn keeps_running_events_and_drops_ended_ones() {
+        let now = Utc::now();
+        let utc = |minutes: i64| {
+            (now + TimeDelta::minutes(minutes))
+                .format(":%Y%m%dT%H%M%SZ")
+                .to_string()
+        };
+        let text = ics(&format!(
+            "{}{}{}",
+            vevent(
+                "ended",
+                "Ended",
+                &utc(-120),
+                &format!("DTEND{}\r\n", utc(-1))
+            ),
+            vevent(
+                "running",
+                "Running",
+                &utc(-60),
+                &format!("DTEND{}\r\n", utc(60))
+            ),
+            // Started two days ago, so sorted by its start
+            vevent(
+                "trip",
+                "Trip",
+                &utc(-2880),
+                &format!("DTEND{}\r\n", utc(1440))
+            ),
+        ));
+        let calendar = mkcalendar(
+            &[source("running", "R", &text)],
+            &Calendar::default(),
+            10,
+            14,
+        )
+        .unwrap();
+        assert_eq!(titles(&calendar), vec![("R", "Trip"), ("R", "Running")]);
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let text = ics(&vevent("late", "Late", &local(1, "150000"), ""));
+        let route = |status, body: &str| mock::Route {
+            path: "/family.ics".to_string(),
+            status,
+            headers: vec![
+                ("ETag".to_string(), "\"v1\"".to_string()),
+                (
+                    "Last-Modified".to_string(),
+                    "Fri, 16 Oct 2026 12:00:00 GMT".to_string(),
+                ),
+            ],
+            body: body.to_string(),
+        };
+        let (base, requests) =
+            mock::serve_routes(vec![route(200, &text), route(304, ""), route(200, &text)]);
+        let source = CalendarSource {
+            location: format!("{}/family.ics", base),
+            label: Some("F".to_string()),
+            http: HttpOptions {
+                auth: Some(Auth::Basic {
+                    user: "me".to_string(),
+                    password: Some("secret".to_string()),
+                }),
+                headers: vec![("X-Api-Key".to_string(), "key".to_string())],
+                ..Default::default()
+            },
+        };
+        let from = Utc::now();
+        let to = from + Days::new(14);
+
+        let feed = load_source(&source, None, from, to).unwrap();
+        assert_eq!(feed.events.len(), 1);
+        assert_eq!(feed.etag.as_deref(), Some("\"v1\""));
+
+        // Not modified, so the events are the ones loaded before
+        let again = load_source(&source, Some(&feed), from, to).unwrap();
+        assert_eq!(again.events, feed.events);
+
+        // Recurrences weren't expanded this far, so the feed is loaded again
+        let later =
+            load_source(&source, Some(&feed), from, feed.window_end + Days::new(1)).unwrap();
+        assert_eq!(later.events.len(), 1);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|x| x.method == "GET"));
+        assert_eq!(requests[0].header("If-None-Match"), None);
+        assert_eq!(requests[1].header("If-None-Match"), Some("\"v1\""));
+        assert_eq!(
+            requests[1].header("If-Modified-Since"),
+            Some("Fri, 16 Oct 2026 12:00:00 GMT")
+        );
+        assert_eq!(requests[2].header("If-None-Match"), None);
+        // `me:secret`
+        assert_eq!(
+            requests[0].header("Authorization"),
+            Some("Basic bWU6c2VjcmV0")
+        );
+        assert_eq!(requests[0].header("X-Api-Key"), Some("key"));
+    }
+
+    #[test]
+    fn failing_sources_are_tracked_on_their_own() {
+        let ok = source(
+            "status-ok",
+            "W",
+            &ics(&vevent("late", "Late", &local(1, "150000"), "")),
+        );
+        let broken = CalendarSource {
+            location: "/nonexistent/family.ics".to_string(),
+            label: Some("F".to_string()),
+            http: Default::default(),
+        };
+        let sources = [ok, broken];
+        let mut calendar = mkcalendar(&sources, &Calendar::default(), 10, 14).unwrap();
+        assert_eq!(calendar.events.len(), 1);
+        let now = Utc::now();
+        for feed in &mut calendar.feeds {
+            feed.status.update_staleness(now, 3600);
+        }
+        let [ok, broken] = &calendar.feeds[..] else {
+            panic!("one feed per source");
+        };
+        assert!(ok.status.last_success.is_some() && !ok.status.stale);
+        assert_eq!(broken.label.as_deref(), Some("F"));
+        assert!(broken.status.last_error.is_some() && broken.status.stale);
+
+        // Failing again it keeps its place, so it is still matched to its source
+        let again = mkcalendar(&sources, &calendar, 10, 14).unwrap();
+        assert_eq!(again.feeds[1].location, "/nonexistent/family.ics");
+        assert!(again.feeds[1].status.last_error.is_some());
+    }
+}
@@ -1,3 +1,5 @@
+use chrono::{NaiveDate, NaiveDateTime};
+
 pub fn mk_time_date() -> (String, String) {
     let now = chrono::Local::now();
     (
@@ -7,3 +9,25 @@ pub fn mk_time_date() -> (String, String) {
             .to_string(),
     )
 }
+
+pub fn parse_ics_datetime(value: &str, allday: &mut bool) -> Option<NaiveDateTime> {
+    // all day event
+    if value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        *allday = true;
+        return date.and_hms_opt(0, 0, 0);
+    }
+
+    // date + time event
+    let fmt = match value.len() {
+        13 => "%Y%m%dT%H%M",
+        15 => "%Y%m%dT%H%M%S",
+        16 => "%Y%m%dT%H%M%SZ",
+        _ => {
+            eprintln!("Don't know how to parse date time string `{}`", value);
+            return None;
+        }
+    };
+
+    NaiveDateTime::parse_from_str(value, fmt).ok()
+}
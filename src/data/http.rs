@@ -0,0 +1,122 @@
+use reqwest::blocking::{Client, RequestBuilder};
+use std::time::Duration;
+
+pub const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub enum Auth {
+    Basic {
+        user: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+}
+
+/// Per-source settings for http(s) requests.
+#[derive(Debug, Clone, Default)]
+pub struct HttpOptions {
+    pub auth: Option<Auth>,
+    pub headers: Vec<(String, String)>,
+    pub timeout: Option<Duration>,
+    pub user_agent: Option<String>,
+}
+
+impl HttpOptions {
+    /// Reads `<prefix>_TOKEN`, or `<prefix>_USER` and `<prefix>_PASSWORD`,
+    /// `<prefix>_HEADERS`, `<prefix>_TIMEOUT` and `<prefix>_USER_AGENT` with
+    /// `var`, which looks up a variable by name.
+    pub fn from_vars(prefix: &str, var: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |suffix: &str| var(&format!("{}_{}", prefix, suffix));
+        let auth = match (var("TOKEN"), var("USER")) {
+            (Some(token), _) => Some(Auth::Bearer(token)),
+            (None, Some(user)) => Some(Auth::Basic {
+                user,
+                password: var("PASSWORD"),
+            }),
+            (None, None) => None,
+        };
+        // Extra headers are given as `Name: value|Other-Name: value`
+        let headers = var("HEADERS")
+            .iter()
+            .flat_map(|x| x.split('|'))
+            .filter_map(|x| x.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        let timeout = var("TIMEOUT").map(|x| {
+            Duration::from_secs(
+                x.parse()
+                    .unwrap_or_else(|_| panic!("{}_TIMEOUT must be an integer (seconds)", prefix)),
+            )
+        });
+        HttpOptions {
+            auth,
+            headers,
+            timeout,
+            user_agent: var("USER_AGENT"),
+        }
+    }
+
+    pub fn client(&self) -> Result<Client, reqwest::Error> {
+        Client::builder()
+            .user_agent(self.user_agent.as_deref().unwrap_or(USER_AGENT))
+            .timeout(self.timeout.unwrap_or(DEFAULT_TIMEOUT))
+            .build()
+    }
+
+    /// Adds the credentials and custom headers to a request.
+    pub fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+        request = match &self.auth {
+            Some(Auth::Basic { user, password }) => request.basic_auth(user, password.as_ref()),
+            Some(Auth::Bearer(token)) => request.bearer_auth(token),
+            None => request,
+        };
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn from_vars() {
+        let vars = HashMap::from([
+            ("ICS_2_USER", "me"),
+            ("ICS_2_PASSWORD", "secret"),
+            (
+                "ICS_2_HEADERS",
+                "Accept-Language: en| X-Api-Key:key:with:colons |junk",
+            ),
+            ("ICS_2_TIMEOUT", "20"),
+            ("ICS_3_TOKEN", "abc"),
+            ("ICS_3_USER", "ignored"),
+        ]);
+        let var = |name: &str| vars.get(name).map(|x| x.to_string());
+
+        let options = HttpOptions::from_vars("ICS_2", var);
+        assert!(matches!(
+            options.auth,
+            Some(Auth::Basic { ref user, password: Some(ref password) })
+                if user == "me" && password == "secret"
+        ));
+        assert_eq!(
+            options.headers,
+            vec![
+                ("Accept-Language".to_string(), "en".to_string()),
+                ("X-Api-Key".to_string(), "key:with:colons".to_string()),
+            ]
+        );
+        assert_eq!(options.timeout, Some(Duration::from_secs(20)));
+        assert_eq!(options.user_agent, None);
+
+        // A token wins over a user
+        let options = HttpOptions::from_vars("ICS_3", var);
+        assert!(matches!(options.auth, Some(Auth::Bearer(ref token)) if token == "abc"));
+        assert!(HttpOptions::from_vars("ICS", var).auth.is_none());
+    }
+}
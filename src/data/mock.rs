@@ -0,0 +1,154 @@
+//! A tiny HTTP server for testing fetches against recorded responses.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+/// Reads `test/fixtures/<name>`.
+pub fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("./test/fixtures/{}", name)).expect("missing fixture")
+}
+
+/// A request as the server saw it.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A response to requests for `path`, with extra headers.
+pub struct Route {
+    pub path: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl From<(&str, u16, String)> for Route {
+    fn from((path, status, body): (&str, u16, String)) -> Self {
+        Route {
+            path: path.to_string(),
+            status,
+            headers: vec![],
+            body,
+        }
+    }
+}
+
+/// Serves `(path, status, body)` routes on a free local port until the test
+/// process exits and returns the server's base url. `{base}` in a body is
+/// replaced by that url so recorded responses can link back to the server,
+/// and `{now}` by the current time for responses that must look fresh.
+/// A path listed more than once answers with each route in turn, repeating the
+/// last one.
+pub fn serve(routes: Vec<(&str, u16, String)>) -> String {
+    serve_routes(routes.into_iter().map(Route::from).collect()).0
+}
+
+/// Like [`serve`], but with response headers, and also returns every request
+/// received so far.
+pub fn serve_routes(routes: Vec<Route>) -> (String, Arc<Mutex<Vec<Request>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let mut routes: Vec<Route> = routes
+        .into_iter()
+        .map(|route| Route {
+            body: route
+                .body
+                .replace("{base}", &base)
+                .replace("{now}", &chrono::Utc::now().to_rfc3339()),
+            ..route
+        })
+        .collect();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let mut headers = Vec::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                if let Some((name, value)) = line.split_once(':') {
+                    headers.push((name.trim().to_string(), value.trim().to_string()));
+                }
+                line.clear();
+            }
+            let mut request = Request {
+                method: request_line
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or("")
+                    .to_string(),
+                path: request_line
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or("")
+                    .split('?')
+                    .next()
+                    .unwrap_or("")
+                    .to_string(),
+                headers,
+                body: String::new(),
+            };
+            let length = request
+                .header("Content-Length")
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            if reader.read_exact(&mut body).is_err() {
+                continue;
+            }
+            request.body = String::from_utf8_lossy(&body).to_string();
+
+            let matching: Vec<usize> = (0..routes.len())
+                .filter(|i| routes[*i].path == request.path)
+                .collect();
+            received.lock().unwrap().push(request);
+            let (status, extra, body) = match matching[..] {
+                [] => (404, vec![], "{}".to_string()),
+                [i] => (
+                    routes[i].status,
+                    routes[i].headers.clone(),
+                    routes[i].body.clone(),
+                ),
+                [i, ..] => {
+                    let route = routes.remove(i);
+                    (route.status, route.headers, route.body)
+                }
+            };
+            let extra: String = extra
+                .iter()
+                .map(|(name, value)| format!("{}: {}\r\n", name, value))
+                .collect();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                extra,
+                body
+            );
+        }
+    });
+
+    (base, requests)
+}
@@ -1,14 +1,32 @@
 use crate::image_gen::create_image;
-use crate::{data::calendar::Calendar, image_gen::Disp};
+use crate::{
+    data::calendar::{Calendar, CalendarSource},
+    data::http::HttpOptions,
+    image_gen::Disp,
+};
 use calendar::mkcalendar;
-use chrono::Utc;
+use chrono::{DateTime, Local, Utc};
 use datetime::mk_time_date;
+use embedded_graphics::primitives::Rectangle;
+use serde::{Deserialize, Serialize};
+use solar::{MoonPhase, SolarDay};
 use std::env::{self, VarError};
+use std::path::PathBuf;
 use std::{cell::RefCell, rc::Rc};
-use weather::{mkweather, WeatherData};
+use units::UnitPreference;
+use weather::{mkprovider, mkweather, WeatherData, WeatherProvider};
 
+pub mod caldav;
 pub mod calendar;
 pub mod datetime;
+pub mod http;
+#[cfg(test)]
+pub mod mock;
+pub mod recurrence;
+pub mod solar;
+pub mod state;
+pub mod timezone;
+pub mod units;
 pub mod weather;
 
 #[derive(Debug, Default)]
@@ -17,45 +35,206 @@ pub struct DisplayData {
     pub calendar: Calendar,
     pub date: String,
     pub time: String,
+    pub now: DateTime<Local>,
+    pub sun: SolarDay,
+    pub moon: MoonPhase,
+    pub weather_status: FetchStatus,
+    /// When the weather was last fetched, whether or not that worked
+    pub weather_attempt: Option<DateTime<Utc>>,
+    /// When the calendar was last loaded, whether or not that worked
+    pub calendar_attempt: Option<DateTime<Utc>>,
 }
+
+/// How fetching the weather or one calendar source has been going.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct FetchStatus {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// Whether the data is older than `RunArgs::stale_after`
+    pub stale: bool,
+}
+
+impl FetchStatus {
+    /// Status of data loaded from the state directory, fetched at `time`.
+    fn restored(time: DateTime<Utc>) -> FetchStatus {
+        FetchStatus {
+            last_success: (time != DateTime::<Utc>::default()).then_some(time),
+            ..Default::default()
+        }
+    }
+
+    fn succeeded(&mut self, time: DateTime<Utc>) {
+        self.last_success = Some(time);
+        self.last_error = None;
+    }
+
+    fn failed(&mut self, error: &dyn std::error::Error) {
+        self.last_error = Some(error.to_string());
+    }
+
+    fn update_staleness(&mut self, now: DateTime<Utc>, stale_after: i64) {
+        self.stale = match self.last_success {
+            Some(time) => (now - time).num_seconds() > stale_after,
+            None => self.last_error.is_some(),
+        };
+    }
+}
+
+/// When each fetch was last attempted, saved so a restart doesn't retry
+/// failing fetches before their TTL is up.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Attempts {
+    weather: Option<DateTime<Utc>>,
+    calendar: Option<DateTime<Utc>>,
+}
+
+/// Whether data from `time` is older than `ttl` seconds. Failed attempts wait
+/// out the TTL as well, rather than being retried on every update.
+fn due(time: DateTime<Utc>, attempt: Option<DateTime<Utc>>, now: DateTime<Utc>, ttl: i64) -> bool {
+    let last = attempt.map_or(time, |attempt| attempt.max(time));
+    (now - last).num_seconds() > ttl
+}
+
 #[derive(Debug)]
 pub struct RunArgs {
     pub lat: f64,
     pub lon: f64,
-    pub ics: String,
+    pub weather: Box<dyn WeatherProvider>,
+    pub sources: Vec<CalendarSource>,
     pub max_events: usize,
+    pub horizon_days: u64,
     pub weather_ttl: i64,
     pub calendar_ttl: i64,
+    /// Seconds after which data that could not be refreshed is flagged as stale
+    pub stale_after: i64,
+    /// Where the last good calendar and weather are kept across restarts
+    pub state_dir: Option<PathBuf>,
+    /// Units the weather is shown in
+    pub units: UnitPreference,
 }
 
-pub fn run(display: &mut Disp, args: &RunArgs, data:  Rc<RefCell<DisplayData>>) {
+impl DisplayData {
+    /// Starts from whatever was saved by the last run, so the display shows the
+    /// last known data rather than nothing while offline.
+    pub fn load(args: &RunArgs) -> DisplayData {
+        match &args.state_dir {
+            Some(dir) => {
+                let weather: WeatherData = state::load(dir, state::WEATHER_FILE);
+                let calendar: Calendar = state::load(dir, state::CALENDAR_FILE);
+                let attempts: Attempts = state::load(dir, state::ATTEMPTS_FILE);
+                DisplayData {
+                    weather_status: FetchStatus::restored(weather.time),
+                    weather,
+                    calendar,
+                    weather_attempt: attempts.weather,
+                    calendar_attempt: attempts.calendar,
+                    ..Default::default()
+                }
+            }
+            None => DisplayData::default(),
+        }
+    }
+}
+
+/// Refreshes whatever is due and draws it all to `display`. Returns where the
+/// clock was drawn, `None` if drawing failed.
+pub fn run(
+    display: &mut Disp,
+    args: &RunArgs,
+    data: Rc<RefCell<DisplayData>>,
+) -> Option<Rectangle> {
     let (time, date) = mk_time_date();
 
     let mut data = data.borrow_mut();
     let now = Utc::now();
     data.date = date;
     data.time = time;
+    data.now = Local::now();
+    data.sun = SolarDay::new(args.lat, args.lon, data.now.date_naive());
+    data.moon = MoonPhase::at(now);
 
-    if (now - data.weather.time).num_seconds() > args.weather_ttl {
-        match mkweather(args.lat, args.lon) {
-            Ok(weather) => data.weather = weather,
-            Err(e) => eprintln!("Failed to fetch calendar: {}", e),
+    let weather_due = due(
+        data.weather.time,
+        data.weather_attempt,
+        now,
+        args.weather_ttl,
+    );
+    let calendar_due = due(
+        data.calendar.time,
+        data.calendar_attempt,
+        now,
+        args.calendar_ttl,
+    );
+    if weather_due {
+        data.weather_attempt = Some(now);
+    }
+    if calendar_due {
+        data.calendar_attempt = Some(now);
+    }
+    if let (Some(dir), true) = (&args.state_dir, weather_due || calendar_due) {
+        let attempts = Attempts {
+            weather: data.weather_attempt,
+            calendar: data.calendar_attempt,
+        };
+        if let Err(e) = state::save(dir, state::ATTEMPTS_FILE, &attempts) {
+            eprintln!("Failed to save fetch attempts: {}", e);
+        }
+    }
+
+    if weather_due {
+        match mkweather(args.weather.as_ref(), args.lat, args.lon) {
+            Ok(weather) => {
+                if let Some(dir) = &args.state_dir {
+                    if let Err(e) = state::save(dir, state::WEATHER_FILE, &weather) {
+                        eprintln!("Failed to save weather: {}", e);
+                    }
+                }
+                data.weather_status.succeeded(weather.time);
+                data.weather = weather;
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch weather: {}", e);
+                data.weather_status.failed(&e);
+            }
         };
     }
-    if (now - data.calendar.time).num_seconds() > args.calendar_ttl {
-        match mkcalendar(args.ics.as_str(), args.max_events) {
+    // Also converts weather restored from a run with other units
+    let units = args.units.resolve(data.weather.units);
+    data.weather.convert(units);
+    if calendar_due {
+        match mkcalendar(
+            &args.sources,
+            &data.calendar,
+            args.max_events,
+            args.horizon_days,
+        ) {
             Ok(calendar) => {
+                if let Some(dir) = &args.state_dir {
+                    if let Err(e) = state::save(dir, state::CALENDAR_FILE, &calendar) {
+                        eprintln!("Failed to save calendar: {}", e);
+                    }
+                }
                 data.calendar = calendar;
             }
             Err(e) => eprintln!("Failed to fetch calendar: {}", e),
         }
     }
+    data.weather_status.update_staleness(now, args.stale_after);
+    for feed in &mut data.calendar.feeds {
+        feed.status.update_staleness(now, args.stale_after);
+    }
 
     eprintln!("{:?}", data);
 
     match create_image(display, &data) {
-        Ok(()) => eprintln!("Successfully updated display"),
-        Err(err) => eprintln!("Failed to update display: {:?}", err),
+        Ok(clock) => {
+            eprintln!("Successfully updated display");
+            Some(clock)
+        }
+        Err(err) => {
+            eprintln!("Failed to update display: {:?}", err);
+            None
+        }
     }
 }
 
@@ -70,7 +249,29 @@ pub fn mk_run_args() -> RunArgs {
         .parse::<f64>()
         .expect("LON must be a float");
 
-    let ics = env::var("ICS").expect("ICS env var not set");
+    let state_dir = state::state_dir();
+
+    let weather = match env::var("WEATHER_PROVIDER") {
+        Ok(s) => mkprovider(&s, state_dir.clone())
+            .expect("WEATHER_PROVIDER must be `nws` or `open-meteo`"),
+        Err(VarError::NotPresent) => mkprovider("nws", state_dir.clone()).unwrap(),
+        Err(VarError::NotUnicode(_)) => panic!("WEATHER_PROVIDER must be unicode"),
+    };
+
+    // Sources are read from ICS, ICS_2, ICS_3, ... until one is missing
+    let sources = std::iter::once("ICS".to_string())
+        .chain((2..).map(|n| format!("ICS_{}", n)))
+        .map_while(|name| {
+            Some(CalendarSource {
+                location: env::var(&name).ok()?,
+                label: env::var(format!("{}_LABEL", name)).ok(),
+                http: HttpOptions::from_vars(&name, |x| env::var(x).ok()),
+            })
+        })
+        .collect::<Vec<_>>();
+    if sources.is_empty() {
+        panic!("ICS env var not set");
+    }
 
     let max_events = match env::var("MAX_EVENTS") {
         Ok(s) => s.parse().expect("MAX_EVENTS must be an integer"),
@@ -78,6 +279,12 @@ pub fn mk_run_args() -> RunArgs {
         Err(VarError::NotUnicode(_)) => panic!("MAX_EVENTS must be unicode"),
     };
 
+    let horizon_days = match env::var("HORIZON_DAYS") {
+        Ok(s) => s.parse().expect("HORIZON_DAYS must be an integer (days)"),
+        Err(VarError::NotPresent) => 14,
+        Err(VarError::NotUnicode(_)) => panic!("HORIZON_DAYS must be unicode"),
+    };
+
     let weather_ttl = match env::var("WEATHER_TTL") {
         Ok(s) => s.parse().expect("WEATHER_TTL must be an integer (seconds)"),
         Err(VarError::NotPresent) => 1800,
@@ -92,12 +299,161 @@ pub fn mk_run_args() -> RunArgs {
         Err(VarError::NotUnicode(_)) => panic!("CALENDAR_TTL must be unicode"),
     };
 
+    let stale_after = match env::var("STALE_AFTER") {
+        Ok(s) => s.parse().expect("STALE_AFTER must be an integer (seconds)"),
+        Err(VarError::NotPresent) => 3 * 3600,
+        Err(VarError::NotUnicode(_)) => panic!("STALE_AFTER must be unicode"),
+    };
+
+    // UNITS picks a system, TEMPERATURE_UNIT and WIND_UNIT override it per
+    // quantity. Anything left unset stays in the provider's units.
+    let mut units = match env::var("UNITS") {
+        Ok(s) => s.parse().expect("UNITS must be `metric` or `imperial`"),
+        Err(VarError::NotPresent) => UnitPreference::default(),
+        Err(VarError::NotUnicode(_)) => panic!("UNITS must be unicode"),
+    };
+    match env::var("TEMPERATURE_UNIT") {
+        Ok(s) => units.temperature = Some(s.parse().expect("TEMPERATURE_UNIT must be `C` or `F`")),
+        Err(VarError::NotPresent) => {}
+        Err(VarError::NotUnicode(_)) => panic!("TEMPERATURE_UNIT must be unicode"),
+    };
+    match env::var("WIND_UNIT") {
+        Ok(s) => {
+            units.speed = Some(
+                s.parse()
+                    .expect("WIND_UNIT must be `km/h`, `mph`, `m/s` or `kn`"),
+            )
+        }
+        Err(VarError::NotPresent) => {}
+        Err(VarError::NotUnicode(_)) => panic!("WIND_UNIT must be unicode"),
+    };
+
     RunArgs {
         lat,
         lon,
-        ics,
+        weather,
+        sources,
         max_events,
+        horizon_days,
         weather_ttl,
         calendar_ttl,
+        stale_after,
+        state_dir,
+        units,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::panel::Panel;
+    use std::cell::Cell;
+    use weather::WeatherError;
+
+    /// Fails every fetch, counting them
+    #[derive(Debug, Default)]
+    struct Down(Rc<Cell<u32>>);
+    impl WeatherProvider for Down {
+        fn fetch(&self, _: f64, _: f64) -> Result<WeatherData, WeatherError> {
+            self.0.set(self.0.get() + 1);
+            Err(WeatherError::NoResults)
+        }
+    }
+
+    fn args(weather: Down, state_dir: Option<PathBuf>) -> RunArgs {
+        RunArgs {
+            lat: 42.3297,
+            lon: -83.0425,
+            weather: Box::new(weather),
+            sources: vec![],
+            max_events: 10,
+            horizon_days: 14,
+            weather_ttl: 600,
+            calendar_ttl: 600,
+            stale_after: 3 * 3600,
+            state_dir,
+            units: Default::default(),
+        }
+    }
+
+    #[test]
+    fn load_restores_saved_state() {
+        let dir = std::env::temp_dir().join(format!("display-state-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let args = args(Down::default(), Some(dir.clone()));
+
+        // Nothing saved yet
+        let data = DisplayData::load(&args);
+        assert_eq!(data.weather.time, DateTime::<Utc>::default());
+        assert!(data.weather_status.last_success.is_none());
+
+        let fetched = Utc::now() - chrono::TimeDelta::hours(1);
+        let weather = WeatherData {
+            icon: "rain".to_string(),
+            time: fetched,
+            ..Default::default()
+        };
+        state::save(&dir, state::WEATHER_FILE, &weather).unwrap();
+        let calendar = Calendar {
+            time: fetched,
+            feeds: vec![calendar::Feed {
+                location: "family.ics".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        state::save(&dir, state::CALENDAR_FILE, &calendar).unwrap();
+
+        let data = DisplayData::load(&args);
+        assert_eq!(data.weather.icon, "rain");
+        assert_eq!(data.weather_status.last_success, Some(fetched));
+        assert_eq!(data.calendar.time, fetched);
+        assert_eq!(data.calendar.feeds[0].location, "family.ics");
+    }
+
+    #[test]
+    fn failed_fetches_wait_for_the_ttl() {
+        let fetches = Rc::new(Cell::new(0));
+        let args = args(Down(fetches.clone()), None);
+        let data = Rc::new(RefCell::new(DisplayData::default()));
+        let mut display = Disp::new(Panel::default());
+        run(&mut display, &args, data.clone());
+        run(&mut display, &args, data.clone());
+        assert_eq!(fetches.get(), 1);
+        assert!(data.borrow().weather_status.last_error.is_some());
+
+        // Once the TTL is up it is tried again
+        data.borrow_mut().weather_attempt = Some(Utc::now() - chrono::TimeDelta::seconds(601));
+        run(&mut display, &args, data.clone());
+        assert_eq!(fetches.get(), 2);
+    }
+
+    #[test]
+    fn restarts_remember_failed_fetches() {
+        let dir = std::env::temp_dir().join(format!("display-attempts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let fetches = Rc::new(Cell::new(0));
+        let args = args(Down(fetches.clone()), Some(dir.clone()));
+        let mut display = Disp::new(Panel::default());
+        let data = Rc::new(RefCell::new(DisplayData::load(&args)));
+        run(&mut display, &args, data.clone());
+        assert_eq!(fetches.get(), 1);
+
+        // A restart picks up when the failed fetch was tried and waits too
+        let restored = DisplayData::load(&args);
+        assert_eq!(restored.weather_attempt, data.borrow().weather_attempt);
+        assert_eq!(restored.calendar_attempt, data.borrow().calendar_attempt);
+        run(&mut display, &args, Rc::new(RefCell::new(restored)));
+        assert_eq!(fetches.get(), 1);
+    }
+
+    #[test]
+    fn due_after_the_later_of_data_and_attempt() {
+        let now = Utc::now();
+        let ago = |s| now - chrono::TimeDelta::seconds(s);
+        assert!(due(ago(700), None, now, 600));
+        assert!(!due(ago(700), Some(ago(10)), now, 600));
+        assert!(!due(ago(10), Some(ago(700)), now, 600));
+        assert!(due(ago(800), Some(ago(700)), now, 600));
     }
 }
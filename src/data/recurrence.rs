@@ -0,0 +1,522 @@
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A parsed `RRULE` value.
+///
+/// Only day-or-coarser frequencies are supported; BYHOUR/BYMINUTE/BYSECOND are
+/// ignored and every instance keeps the time of day of `DTSTART`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<NaiveDateTime>,
+    /// Whether `until` was given in UTC rather than in the zone of `DTSTART`.
+    pub until_utc: bool,
+    pub by_day: Vec<(Option<i32>, Weekday)>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+    pub by_set_pos: Vec<i32>,
+    pub wkst: Weekday,
+}
+
+#[derive(Debug)]
+pub struct RuleError(String);
+impl std::fmt::Display for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unsupported RRULE: {}", self.0)
+    }
+}
+impl std::error::Error for RuleError {}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    Some(match s {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn parse_list<T: FromStr>(value: &str) -> Option<Vec<T>> {
+    value.split(',').map(|x| x.trim().parse().ok()).collect()
+}
+
+/// Parses an `UNTIL` value. Date-only values are inclusive of the whole day.
+fn parse_until(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim_end_matches('Z');
+    if value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()?
+            .and_hms_opt(23, 59, 59);
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()
+}
+
+impl FromStr for RecurrenceRule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || RuleError(s.to_string());
+        let mut rule = RecurrenceRule {
+            freq: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            until_utc: false,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+            by_set_pos: Vec::new(),
+            wkst: Weekday::Mon,
+        };
+        let mut freq = None;
+
+        for part in s.split(';').filter(|x| !x.is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(err)?;
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(err()),
+                    })
+                }
+                "INTERVAL" => rule.interval = value.parse().map_err(|_| err())?,
+                "COUNT" => rule.count = Some(value.parse().map_err(|_| err())?),
+                "UNTIL" => {
+                    rule.until = Some(parse_until(value).ok_or_else(err)?);
+                    rule.until_utc = value.ends_with('Z');
+                }
+                "BYDAY" => {
+                    rule.by_day = value
+                        .split(',')
+                        .map(|day| {
+                            let day = day.trim();
+                            let split = day.len().checked_sub(2)?;
+                            // `get` rather than slicing, which panics inside a
+                            // multi-byte character
+                            let ordinal = match day.get(..split)? {
+                                "" => None,
+                                n => Some(n.trim_start_matches('+').parse().ok()?),
+                            };
+                            Some((ordinal, parse_weekday(day.get(split..)?)?))
+                        })
+                        .collect::<Option<_>>()
+                        .ok_or_else(err)?
+                }
+                "BYMONTHDAY" => rule.by_month_day = parse_list(value).ok_or_else(err)?,
+                "BYMONTH" => rule.by_month = parse_list(value).ok_or_else(err)?,
+                "BYSETPOS" => rule.by_set_pos = parse_list(value).ok_or_else(err)?,
+                "WKST" => rule.wkst = parse_weekday(value).ok_or_else(err)?,
+                _ => {}
+            }
+        }
+
+        rule.freq = freq.ok_or_else(err)?;
+        if rule.interval == 0 {
+            return Err(err());
+        }
+        Ok(rule)
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let next = first + Months::new(1);
+    (next - first).num_days() as u32
+}
+
+/// Resolves a possibly negative BYMONTHDAY against the given month.
+fn month_day(year: i32, month: u32, day: i32) -> Option<NaiveDate> {
+    let len = days_in_month(year, month) as i32;
+    let day = if day < 0 { len + day + 1 } else { day };
+    if day < 1 || day > len {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year, month, day as u32)
+}
+
+/// All dates between `first` and `last` (inclusive) matching a BYDAY entry,
+/// where an ordinal counts occurrences of the weekday within that span.
+fn weekdays_in(
+    first: NaiveDate,
+    last: NaiveDate,
+    ordinal: Option<i32>,
+    weekday: Weekday,
+) -> Vec<NaiveDate> {
+    let matching: Vec<NaiveDate> = first
+        .iter_days()
+        .take_while(|d| *d <= last)
+        .filter(|d| d.weekday() == weekday)
+        .collect();
+    match ordinal {
+        None => matching,
+        Some(n) if n > 0 => matching.get(n as usize - 1).copied().into_iter().collect(),
+        Some(n) => matching
+            .len()
+            .checked_sub(n.unsigned_abs() as usize)
+            .and_then(|i| matching.get(i).copied())
+            .into_iter()
+            .collect(),
+    }
+}
+
+impl RecurrenceRule {
+    fn matches_month(&self, date: NaiveDate) -> bool {
+        self.by_month.is_empty() || self.by_month.contains(&date.month())
+    }
+
+    fn matches_month_day(&self, date: NaiveDate) -> bool {
+        self.by_month_day.is_empty()
+            || self
+                .by_month_day
+                .iter()
+                .any(|d| month_day(date.year(), date.month(), *d) == Some(date))
+    }
+
+    fn matches_weekday(&self, date: NaiveDate) -> bool {
+        self.by_day.is_empty() || self.by_day.iter().any(|(_, w)| *w == date.weekday())
+    }
+
+    /// Candidate dates for the month containing `month`, used by both MONTHLY and
+    /// YEARLY rules.
+    fn month_candidates(&self, year: i32, month: u32, dtstart: NaiveDate) -> Vec<NaiveDate> {
+        let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+        let last = month_day(year, month, -1).unwrap();
+
+        if !self.by_day.is_empty() {
+            self.by_day
+                .iter()
+                .flat_map(|(n, w)| weekdays_in(first, last, *n, *w))
+                .filter(|d| self.matches_month_day(*d))
+                .collect()
+        } else if !self.by_month_day.is_empty() {
+            self.by_month_day
+                .iter()
+                .filter_map(|d| month_day(year, month, *d))
+                .collect()
+        } else {
+            month_day(year, month, dtstart.day() as i32)
+                .into_iter()
+                .collect()
+        }
+    }
+
+    /// Candidate dates for the `index`th period after the one containing `dtstart`.
+    fn period_candidates(&self, dtstart: NaiveDate, index: u32) -> Option<Vec<NaiveDate>> {
+        let step = index.checked_mul(self.interval)?;
+        let mut dates = match self.freq {
+            Frequency::Daily => {
+                let day = dtstart.checked_add_days(Days::new(step as u64))?;
+                let keep = self.matches_month(day)
+                    && self.matches_month_day(day)
+                    && self.matches_weekday(day);
+                if keep {
+                    vec![day]
+                } else {
+                    vec![]
+                }
+            }
+            Frequency::Weekly => {
+                let offset = dtstart.weekday().days_since(self.wkst);
+                let week = dtstart
+                    .checked_sub_days(Days::new(offset as u64))?
+                    .checked_add_days(Days::new(step as u64 * 7))?;
+                week.iter_days()
+                    .take(7)
+                    .filter(|d| {
+                        if self.by_day.is_empty() {
+                            d.weekday() == dtstart.weekday()
+                        } else {
+                            self.matches_weekday(*d)
+                        }
+                    })
+                    .filter(|d| self.matches_month(*d))
+                    .collect()
+            }
+            Frequency::Monthly => {
+                let month = dtstart.with_day(1)?.checked_add_months(Months::new(step))?;
+                if !self.matches_month(month) {
+                    vec![]
+                } else {
+                    self.month_candidates(month.year(), month.month(), dtstart)
+                }
+            }
+            Frequency::Yearly => {
+                let year = dtstart.year() + step as i32;
+                if !self.by_month.is_empty() {
+                    self.by_month
+                        .iter()
+                        .filter(|m| (1..=12).contains(*m))
+                        .flat_map(|m| self.month_candidates(year, *m, dtstart))
+                        .collect()
+                } else if !self.by_day.is_empty() && self.by_month_day.is_empty() {
+                    let first = NaiveDate::from_ymd_opt(year, 1, 1)?;
+                    let last = NaiveDate::from_ymd_opt(year, 12, 31)?;
+                    self.by_day
+                        .iter()
+                        .flat_map(|(n, w)| weekdays_in(first, last, *n, *w))
+                        .collect()
+                } else if !self.by_month_day.is_empty() {
+                    (1..=12)
+                        .flat_map(|m| self.month_candidates(year, m, dtstart))
+                        .collect()
+                } else {
+                    NaiveDate::from_ymd_opt(year, dtstart.month(), dtstart.day())
+                        .into_iter()
+                        .collect()
+                }
+            }
+        };
+
+        dates.sort();
+        dates.dedup();
+
+        if !self.by_set_pos.is_empty() {
+            let len = dates.len() as i32;
+            let mut picked: Vec<NaiveDate> = self
+                .by_set_pos
+                .iter()
+                .filter_map(|pos| {
+                    let i = if *pos < 0 { len + pos } else { pos - 1 };
+                    (0..len).contains(&i).then(|| dates[i as usize])
+                })
+                .collect();
+            picked.sort();
+            picked.dedup();
+            dates = picked;
+        }
+
+        Some(dates)
+    }
+
+    /// First day of the `index`th period, used to know when to stop iterating.
+    fn period_start(&self, dtstart: NaiveDate, index: u32) -> Option<NaiveDate> {
+        let step = index.checked_mul(self.interval)?;
+        match self.freq {
+            Frequency::Daily => dtstart.checked_add_days(Days::new(step as u64)),
+            Frequency::Weekly => dtstart
+                .checked_sub_days(Days::new(dtstart.weekday().days_since(self.wkst) as u64))?
+                .checked_add_days(Days::new(step as u64 * 7)),
+            Frequency::Monthly => dtstart.with_day(1)?.checked_add_months(Months::new(step)),
+            Frequency::Yearly => {
+                NaiveDate::from_ymd_opt(dtstart.year().checked_add(step as i32)?, 1, 1)
+            }
+        }
+    }
+
+    /// A period index safely before the first one that can reach `from`.
+    ///
+    /// Rules with a COUNT have to be walked from the start to count instances.
+    fn first_index(&self, dtstart: NaiveDate, from: NaiveDate) -> u32 {
+        if self.count.is_some() || from <= dtstart {
+            return 0;
+        }
+        let days = (from - dtstart).num_days();
+        let periods = match self.freq {
+            Frequency::Daily => days,
+            Frequency::Weekly => days / 7,
+            Frequency::Monthly => days / 31,
+            Frequency::Yearly => days / 366,
+        } / self.interval as i64;
+        (periods - 1).clamp(0, u32::MAX as i64) as u32
+    }
+}
+
+/// The recurrence properties of a component: `RRULE`, `RDATE` and `EXDATE`.
+#[derive(Debug, Clone, Default)]
+pub struct Recurrence {
+    pub rule: Option<RecurrenceRule>,
+    pub rdates: Vec<NaiveDateTime>,
+    pub exdates: Vec<NaiveDateTime>,
+    /// `EXDATE`s without a time, which remove every instance on that day.
+    pub exdates_allday: Vec<NaiveDate>,
+}
+
+impl Recurrence {
+    /// Start times of the instances beginning within `[from, to]`.
+    ///
+    /// `dtstart` is always an instance, whether or not it matches the rule.
+    pub fn expand(
+        &self,
+        dtstart: NaiveDateTime,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Vec<NaiveDateTime> {
+        let mut starts = vec![dtstart];
+
+        if let Some(rule) = &self.rule {
+            let time: NaiveTime = dtstart.time();
+            let date = dtstart.date();
+            let mut emitted = 1;
+
+            'periods: for index in rule.first_index(date, from.date()).. {
+                match rule.period_start(date, index) {
+                    Some(start) if start.and_time(NaiveTime::MIN) <= to => {}
+                    _ => break,
+                }
+                let Some(candidates) = rule.period_candidates(date, index) else {
+                    break;
+                };
+                for start in candidates.into_iter().map(|d| d.and_time(time)) {
+                    if start <= dtstart {
+                        continue;
+                    }
+                    if rule.count.is_some_and(|count| emitted >= count)
+                        || rule.until.is_some_and(|until| start > until)
+                        || start > to
+                    {
+                        break 'periods;
+                    }
+                    starts.push(start);
+                    emitted += 1;
+                }
+            }
+        }
+
+        starts.extend(&self.rdates);
+        starts.retain(|x| {
+            (from..=to).contains(x)
+                && !self.exdates.contains(x)
+                && !self.exdates_allday.contains(&x.date())
+        });
+        starts.sort();
+        starts.dedup();
+        starts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dt(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%S").unwrap()
+    }
+
+    fn run(dtstart: &str, rule: &str, end: &str) -> Vec<String> {
+        let recurrence = Recurrence {
+            rule: Some(rule.parse().unwrap()),
+            ..Default::default()
+        };
+        recurrence
+            .expand(dt(dtstart), dt(dtstart), dt(end))
+            .iter()
+            .map(|x| x.format("%Y%m%dT%H%M%S").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn weekly_byday_with_count() {
+        assert_eq!(
+            run(
+                "20260105T090000",
+                "FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4",
+                "20261231T000000"
+            ),
+            [
+                "20260105T090000",
+                "20260107T090000",
+                "20260112T090000",
+                "20260114T090000"
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_last_weekday_and_until() {
+        assert_eq!(
+            run(
+                "20260130T170000",
+                "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1;UNTIL=20260430T000000Z",
+                "20261231T000000"
+            ),
+            ["20260130T170000", "20260227T170000", "20260331T170000"]
+        );
+    }
+
+    #[test]
+    fn monthly_bymonthday_skips_short_months() {
+        assert_eq!(
+            run(
+                "20260131T080000",
+                "FREQ=MONTHLY;BYMONTHDAY=31",
+                "20260601T000000"
+            ),
+            ["20260131T080000", "20260331T080000", "20260531T080000"]
+        );
+    }
+
+    #[test]
+    fn yearly_nth_weekday_with_interval() {
+        assert_eq!(
+            run(
+                "20261126T120000",
+                "FREQ=YEARLY;INTERVAL=2;BYMONTH=11;BYDAY=4TH",
+                "20310101T000000"
+            ),
+            ["20261126T120000", "20281123T120000", "20301128T120000"]
+        );
+    }
+
+    #[test]
+    fn window_skips_ahead_without_count() {
+        let recurrence = Recurrence {
+            rule: Some("FREQ=DAILY;INTERVAL=3".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            recurrence.expand(
+                dt("20000101T070000"),
+                dt("20260101T000000"),
+                dt("20260107T000000")
+            ),
+            [dt("20260102T070000"), dt("20260105T070000")]
+        );
+    }
+
+    #[test]
+    fn non_ascii_byday_is_rejected() {
+        for byday in ["1é", "1éO", "é", "MO,2ñU"] {
+            let rule = format!("FREQ=MONTHLY;BYDAY={}", byday);
+            assert!(rule.parse::<RecurrenceRule>().is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn rdate_and_exdate() {
+        let recurrence = Recurrence {
+            rule: Some("FREQ=DAILY;COUNT=3".parse().unwrap()),
+            rdates: vec![dt("20260310T100000")],
+            exdates: vec![dt("20260302T100000")],
+            exdates_allday: vec![],
+        };
+        assert_eq!(
+            recurrence.expand(
+                dt("20260301T100000"),
+                dt("20260301T000000"),
+                dt("20260401T000000")
+            ),
+            [
+                dt("20260301T100000"),
+                dt("20260303T100000"),
+                dt("20260310T100000")
+            ]
+        );
+    }
+}
@@ -0,0 +1,349 @@
+use chrono::{DateTime, NaiveDate, TimeDelta, Timelike, Utc};
+
+/// Elevation of the sun's centre at sunrise and sunset, allowing for
+/// refraction and the radius of the disc.
+const SUNRISE_ELEVATION: f64 = -0.833;
+/// The sun is this far below the horizon at the start and end of civil twilight.
+const CIVIL_ELEVATION: f64 = -6.0;
+
+/// When the sun rises and sets on one day. Times are `None` when the sun does
+/// not cross that elevation at all, e.g. in polar day or night.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SolarDay {
+    pub date: NaiveDate,
+    /// Start of civil twilight
+    pub dawn: Option<DateTime<Utc>>,
+    pub sunrise: Option<DateTime<Utc>>,
+    pub sunset: Option<DateTime<Utc>>,
+    /// End of civil twilight
+    pub dusk: Option<DateTime<Utc>>,
+    /// Zero in polar night and 24 hours in polar day
+    pub day_length: TimeDelta,
+}
+
+impl SolarDay {
+    /// The sun's day at a location, where `date` is the local date there.
+    pub fn new(lat: f64, lon: f64, date: NaiveDate) -> SolarDay {
+        let (sunrise, sunset) = crossings(lat, lon, date, SUNRISE_ELEVATION);
+        let (dawn, dusk) = crossings(lat, lon, date, CIVIL_ELEVATION);
+        let day_length = match (sunrise, sunset) {
+            (Some(rise), Some(set)) => set - rise,
+            _ if elevation(lat, lon, solar_noon(lon, date)) > SUNRISE_ELEVATION => {
+                TimeDelta::hours(24)
+            }
+            _ => TimeDelta::zero(),
+        };
+
+        SolarDay {
+            date,
+            dawn,
+            sunrise,
+            sunset,
+            dusk,
+            day_length,
+        }
+    }
+}
+
+/// Declination of the sun in radians and the equation of time in minutes.
+fn position(time: DateTime<Utc>) -> (f64, f64) {
+    // days since J2000
+    let n = time.timestamp() as f64 / 86400.0 + 2440587.5 - 2451545.0;
+
+    let mean_long = (280.460 + 0.9856474 * n).to_radians();
+    let mean_anom = (357.528 + 0.9856003 * n).to_radians();
+
+    let eclip_long =
+        mean_long + (1.915 * mean_anom.sin() + 0.020 * (2.0 * mean_anom).sin()).to_radians();
+
+    let obliq = (23.439 - 0.0000004 * n).to_radians();
+
+    let decl = (obliq.sin() * eclip_long.sin()).asin();
+
+    let y = (obliq / 2.0).tan().powi(2);
+
+    let eq_time = 4.0
+        * (y * (2.0 * mean_long).sin() - 2.0 * 0.0167 * mean_anom.sin()
+            + 4.0 * 0.0167 * y * mean_anom.sin() * (2.0 * mean_long).cos()
+            - 0.5 * y * y * (4.0 * mean_long).sin()
+            - 1.25 * 0.0167 * 0.0167 * (2.0 * mean_anom).sin())
+        .to_degrees();
+
+    (decl, eq_time)
+}
+
+/// Elevation of the sun above the horizon in degrees.
+pub fn elevation(lat_deg: f64, lon_deg: f64, now: DateTime<Utc>) -> f64 {
+    let lat = lat_deg.to_radians();
+    let (decl, eq_time) = position(now);
+
+    // solar time
+    let time_utc_min = now.hour() as f64 * 60.0 + now.minute() as f64 + now.second() as f64 / 60.0;
+
+    let true_solar_time = (time_utc_min + eq_time + 4.0 * lon_deg) % 1440.0;
+
+    let hour_angle = if true_solar_time / 4.0 < 0.0 {
+        true_solar_time / 4.0 + 180.0
+    } else {
+        true_solar_time / 4.0 - 180.0
+    }
+    .to_radians();
+
+    (lat.sin() * decl.sin() + lat.cos() * decl.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees()
+}
+
+fn minutes(minutes: f64) -> TimeDelta {
+    TimeDelta::milliseconds((minutes * 60_000.0).round() as i64)
+}
+
+/// When the sun is highest on `date` at longitude `lon`.
+fn solar_noon(lon: f64, date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    // Start from mean noon and correct with the equation of time there
+    let (_, eq_time) = position(midnight + minutes(720.0 - 4.0 * lon));
+    midnight + minutes(720.0 - 4.0 * lon - eq_time)
+}
+
+/// When the sun passes `target` degrees of elevation in the morning and the
+/// evening of `date`.
+fn crossings(
+    lat: f64,
+    lon: f64,
+    date: NaiveDate,
+    target: f64,
+) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let noon = solar_noon(lon, date);
+    let lat = lat.to_radians();
+
+    // Half the time between the crossings, from the declination at `time`
+    let half_arc = |time: DateTime<Utc>| {
+        let (decl, _) = position(time);
+        let cos_ha =
+            (target.to_radians().sin() - lat.sin() * decl.sin()) / (lat.cos() * decl.cos());
+        // Out of range when the sun stays above or below `target` all day
+        (-1.0..=1.0)
+            .contains(&cos_ha)
+            .then(|| minutes(4.0 * cos_ha.acos().to_degrees()))
+    };
+
+    // The declination changes a little between noon and the crossing, so
+    // refine once using the declination at the first estimate
+    let rise = half_arc(noon).and_then(|arc| half_arc(noon - arc).map(|arc| noon - arc));
+    let set = half_arc(noon).and_then(|arc| half_arc(noon + arc).map(|arc| noon + arc));
+    (rise, set)
+}
+
+/// The moon as seen from the earth at one moment.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MoonPhase {
+    /// How far the moon is through its cycle in degrees: 0 new, 90 first
+    /// quarter, 180 full and 270 last quarter
+    pub angle: f64,
+    /// Fraction of the disc that is lit, from 0 to 1
+    pub illumination: f64,
+}
+
+impl MoonPhase {
+    /// The phase at `time`, after Meeus, Astronomical Algorithms ch. 48. Good
+    /// to about a percent of illumination, which is plenty for a glyph.
+    pub fn at(time: DateTime<Utc>) -> MoonPhase {
+        // centuries since J2000
+        let t = (time.timestamp() as f64 / 86400.0 + 2440587.5 - 2451545.0) / 36525.0;
+
+        // mean elongation of the moon, anomaly of the sun, anomaly of the moon
+        let d = (297.8501921 + 445267.1114034 * t).rem_euclid(360.0);
+        let m = (357.5291092 + 35999.0502909 * t).to_radians();
+        let mp = (134.9633964 + 477198.8675055 * t).to_radians();
+
+        let phase_angle = 180.0 - d - 6.289 * mp.sin() + 2.100 * m.sin()
+            - 1.274 * (2.0 * d.to_radians() - mp).sin()
+            - 0.658 * (2.0 * d.to_radians()).sin()
+            - 0.214 * (2.0 * mp).sin()
+            - 0.110 * d.to_radians().sin();
+        let illumination = (1.0 + phase_angle.to_radians().cos()) / 2.0;
+
+        // The phase angle goes 180 -> 0 -> 180 over a cycle, unfold it
+        let elongation = 180.0 - phase_angle.rem_euclid(360.0);
+        let angle = if d < 180.0 {
+            elongation.abs()
+        } else {
+            360.0 - elongation.abs()
+        };
+
+        MoonPhase {
+            angle,
+            illumination,
+        }
+    }
+
+    /// One of the eight moon glyphs U+1F311 to U+1F318
+    pub fn glyph(&self) -> &'static str {
+        [
+            "\u{1F311}",
+            "\u{1F312}",
+            "\u{1F313}",
+            "\u{1F314}",
+            "\u{1F315}",
+            "\u{1F316}",
+            "\u{1F317}",
+            "\u{1F318}",
+        ][(self.angle / 45.0).round() as usize % 8]
+    }
+
+    pub fn name(&self) -> &'static str {
+        [
+            "New Moon",
+            "Waxing Crescent",
+            "First Quarter",
+            "Waxing Gibbous",
+            "Full Moon",
+            "Waning Gibbous",
+            "Last Quarter",
+            "Waning Crescent",
+        ][(self.angle / 45.0).round() as usize % 8]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn assert_near(actual: Option<DateTime<Utc>>, expected: &str) {
+        let actual = actual.expect(expected);
+        let diff = (actual - at(expected)).num_seconds().abs();
+        assert!(diff <= 120, "{} is not near {}", actual, expected);
+    }
+
+    #[test]
+    fn published_times() {
+        // Published sunrise and sunset, to the minute
+        let london = SolarDay::new(
+            51.5074,
+            -0.1278,
+            NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(),
+        );
+        assert_near(london.sunrise, "2024-06-21T03:43:00Z");
+        assert_near(london.sunset, "2024-06-21T20:21:00Z");
+
+        let sydney = SolarDay::new(
+            -33.8688,
+            151.2093,
+            NaiveDate::from_ymd_opt(2024, 12, 21).unwrap(),
+        );
+        assert_near(sydney.sunrise, "2024-12-20T18:41:00Z");
+        assert_near(sydney.sunset, "2024-12-21T09:05:00Z");
+
+        let reykjavik = SolarDay::new(
+            64.1466,
+            -21.9426,
+            NaiveDate::from_ymd_opt(2024, 12, 21).unwrap(),
+        );
+        assert_near(reykjavik.sunrise, "2024-12-21T11:22:00Z");
+        assert_near(reykjavik.sunset, "2024-12-21T15:29:00Z");
+        assert!(
+            (reykjavik.day_length - TimeDelta::minutes(247))
+                .num_minutes()
+                .abs()
+                <= 2
+        );
+
+        // Near the equator days are about 12 hours all year
+        let quito = SolarDay::new(
+            -0.1807,
+            -78.4678,
+            NaiveDate::from_ymd_opt(2024, 3, 20).unwrap(),
+        );
+        assert!(
+            (quito.day_length - TimeDelta::minutes(727))
+                .num_minutes()
+                .abs()
+                <= 3
+        );
+    }
+
+    #[test]
+    fn twilight() {
+        let date = NaiveDate::from_ymd_opt(2026, 10, 17).unwrap();
+        let detroit = SolarDay::new(42.3297, -83.0425, date);
+        let (dawn, sunrise) = (detroit.dawn.unwrap(), detroit.sunrise.unwrap());
+        let (sunset, dusk) = (detroit.sunset.unwrap(), detroit.dusk.unwrap());
+        assert!(dawn < sunrise && sunrise < sunset && sunset < dusk);
+
+        // The sun is where it should be at each of them
+        for (time, target) in [
+            (dawn, CIVIL_ELEVATION),
+            (sunrise, SUNRISE_ELEVATION),
+            (sunset, SUNRISE_ELEVATION),
+            (dusk, CIVIL_ELEVATION),
+        ] {
+            let elevation = elevation(42.3297, -83.0425, time);
+            assert!(
+                (elevation - target).abs() < 0.1,
+                "{} at {}",
+                elevation,
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn polar() {
+        // Tromsø has polar night from late November to mid January, but the
+        // sun comes close enough to the horizon for civil twilight at noon
+        let winter = SolarDay::new(
+            69.6496,
+            18.956,
+            NaiveDate::from_ymd_opt(2024, 12, 21).unwrap(),
+        );
+        assert_eq!((winter.sunrise, winter.sunset), (None, None));
+        assert_eq!(winter.day_length, TimeDelta::zero());
+        assert!(winter.dawn.is_some() && winter.dusk.is_some());
+
+        // and midnight sun from late May to late July
+        let summer = SolarDay::new(
+            69.6496,
+            18.956,
+            NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(),
+        );
+        assert_eq!((summer.sunrise, summer.sunset), (None, None));
+        assert_eq!((summer.dawn, summer.dusk), (None, None));
+        assert_eq!(summer.day_length, TimeDelta::hours(24));
+
+        // At the South Pole the sun stays down for the whole southern winter
+        let pole = SolarDay::new(-90.0, 0.0, NaiveDate::from_ymd_opt(2024, 6, 21).unwrap());
+        assert_eq!(pole.day_length, TimeDelta::zero());
+        assert_eq!((pole.dawn, pole.dusk), (None, None));
+    }
+
+    #[test]
+    fn moon_phases() {
+        // Published times of the principal phases in April 2024
+        for (time, glyph, illumination) in [
+            ("2024-04-08T18:21:00Z", "\u{1F311}", 0.0),
+            ("2024-04-15T19:13:00Z", "\u{1F313}", 0.5),
+            ("2024-04-23T23:49:00Z", "\u{1F315}", 1.0),
+            ("2024-05-01T11:27:00Z", "\u{1F317}", 0.5),
+        ] {
+            let moon = MoonPhase::at(at(time));
+            assert_eq!(moon.glyph(), glyph, "{:?} at {}", moon, time);
+            assert!(
+                (moon.illumination - illumination).abs() < 0.02,
+                "{:?} at {}",
+                moon,
+                time
+            );
+        }
+
+        // Crescents either side of the new moon
+        let waxing = MoonPhase::at(at("2024-04-11T12:00:00Z"));
+        assert_eq!(waxing.name(), "Waxing Crescent");
+        let waning = MoonPhase::at(at("2024-04-05T12:00:00Z"));
+        assert_eq!(waning.name(), "Waning Crescent");
+    }
+}
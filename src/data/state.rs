@@ -0,0 +1,99 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+pub const CALENDAR_FILE: &str = "calendar.json";
+pub const WEATHER_FILE: &str = "weather.json";
+pub const GRIDPOINT_FILE: &str = "gridpoint.json";
+pub const ATTEMPTS_FILE: &str = "attempts.json";
+
+/// `$STATE_DIR`, falling back to `$XDG_STATE_HOME/calendar-display` and then
+/// `~/.local/state/calendar-display`.
+pub fn state_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("STATE_DIR") {
+        return Some(dir.into());
+    }
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+    Some(base.join(env!("CARGO_PKG_NAME")))
+}
+
+/// Reads a value saved by [`save`], or the default if there is none yet.
+pub fn load<T: DeserializeOwned + Default>(dir: &Path, name: &str) -> T {
+    let path = dir.join(name);
+    match File::open(&path) {
+        Ok(file) => serde_json::from_reader(BufReader::new(file)).unwrap_or_else(|e| {
+            eprintln!("Ignoring unreadable state {}: {}", path.display(), e);
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
+
+/// Writes `value` to a temporary file first so a power cut never leaves a
+/// half-written state file behind.
+pub fn save<T: Serialize>(dir: &Path, name: &str, value: &T) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(name);
+    let tmp = path.with_extension("json.tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    serde_json::to_writer(&mut writer, value)?;
+    writer.into_inner()?.sync_all()?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// An empty directory for one test, named after it so tests don't collide
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("state-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn saved_values_load_back() {
+        // Not created yet, `save` makes it
+        let dir = temp_dir("roundtrip").join("nested");
+        let value = BTreeMap::from([("a".to_string(), 1), ("b".to_string(), 2)]);
+        save(&dir, WEATHER_FILE, &value).unwrap();
+        assert_eq!(load::<BTreeMap<String, i32>>(&dir, WEATHER_FILE), value);
+
+        // Overwriting replaces the file and leaves no temporary one behind
+        let value = BTreeMap::from([("c".to_string(), 3)]);
+        save(&dir, WEATHER_FILE, &value).unwrap();
+        assert_eq!(load::<BTreeMap<String, i32>>(&dir, WEATHER_FILE), value);
+        let files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|x| x.unwrap().file_name())
+            .collect();
+        assert_eq!(files, [WEATHER_FILE]);
+    }
+
+    #[test]
+    fn missing_or_corrupt_state_loads_the_default() {
+        let dir = temp_dir("fallback");
+        assert_eq!(
+            load::<Vec<String>>(&dir, CALENDAR_FILE),
+            Vec::<String>::new()
+        );
+
+        fs::create_dir_all(&dir).unwrap();
+        for text in ["", "{\"events\": [", "not json", "{\"a\": 1}"] {
+            fs::write(dir.join(CALENDAR_FILE), text).unwrap();
+            assert_eq!(
+                load::<Vec<String>>(&dir, CALENDAR_FILE),
+                Vec::<String>::new(),
+                "{:?}",
+                text
+            );
+        }
+    }
+}
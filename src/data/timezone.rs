@@ -0,0 +1,285 @@
+use crate::data::datetime::parse_ics_datetime;
+use crate::data::recurrence::Recurrence;
+use chrono::{
+    DateTime, FixedOffset, Local, LocalResult, NaiveDateTime, Offset, TimeDelta, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use ical::parser::ical::component::IcalTimeZone;
+use std::collections::HashMap;
+
+/// One STANDARD or DAYLIGHT block of a VTIMEZONE.
+#[derive(Debug, Clone)]
+struct Transition {
+    /// Onset as wall time in `offset_from`.
+    start: NaiveDateTime,
+    offset_from: FixedOffset,
+    offset_to: FixedOffset,
+    recurrence: Recurrence,
+}
+
+/// A time zone defined by a VTIMEZONE block inside the ICS file itself.
+#[derive(Debug, Clone, Default)]
+pub struct VTimezone {
+    transitions: Vec<Transition>,
+}
+
+/// Embedded VTIMEZONE definitions keyed by TZID.
+pub type Timezones = HashMap<String, VTimezone>;
+
+fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let sign = match value.get(0..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let hours: i32 = value.get(1..3)?.parse().ok()?;
+    let minutes: i32 = value.get(3..5)?.parse().ok()?;
+    let seconds: i32 = match value.get(5..7) {
+        Some(s) => s.parse().ok()?,
+        None => 0,
+    };
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60 + seconds))
+}
+
+pub fn parse_vtimezones(zones: Vec<IcalTimeZone>) -> Timezones {
+    zones
+        .into_iter()
+        .filter_map(|zone| {
+            let tzid = zone
+                .properties
+                .into_iter()
+                .find(|p| p.name == "TZID")?
+                .value?;
+            let transitions = zone
+                .transitions
+                .into_iter()
+                .filter_map(|transition| {
+                    let mut start = None;
+                    let mut offset_from = None;
+                    let mut offset_to = None;
+                    let mut recurrence = Recurrence::default();
+                    for prop in transition.properties {
+                        let Some(v) = prop.value else {
+                            continue;
+                        };
+                        match prop.name.as_str() {
+                            "DTSTART" => start = parse_ics_datetime(&v, &mut false),
+                            "TZOFFSETFROM" => offset_from = parse_offset(&v),
+                            "TZOFFSETTO" => offset_to = parse_offset(&v),
+                            "RRULE" => recurrence.rule = v.parse().ok(),
+                            "RDATE" => recurrence.rdates.extend(
+                                v.split(',')
+                                    .filter_map(|x| parse_ics_datetime(x, &mut false)),
+                            ),
+                            _ => {}
+                        }
+                    }
+                    Some(Transition {
+                        start: start?,
+                        offset_from: offset_from?,
+                        offset_to: offset_to?,
+                        recurrence,
+                    })
+                })
+                .collect();
+            Some((tzid, VTimezone { transitions }))
+        })
+        .collect()
+}
+
+impl VTimezone {
+    /// The UTC offset in effect at `at`, which is a wall time if `at_is_utc` is
+    /// false and a UTC time otherwise.
+    fn offset(&self, at: NaiveDateTime, at_is_utc: bool) -> FixedOffset {
+        let latest = |from: NaiveDateTime| {
+            self.transitions
+                .iter()
+                .flat_map(|t| {
+                    t.recurrence
+                        .expand(t.start, from, at + TimeDelta::days(1))
+                        .into_iter()
+                        .map(move |wall| {
+                            (
+                                if at_is_utc {
+                                    wall - t.offset_from
+                                } else {
+                                    wall
+                                },
+                                t,
+                            )
+                        })
+                })
+                .filter(|(onset, _)| *onset <= at)
+                .max_by_key(|(onset, _)| *onset)
+        };
+
+        latest(at - TimeDelta::days(400))
+            .or_else(|| latest(NaiveDateTime::MIN))
+            .map(|(_, t)| t.offset_to)
+            .or_else(|| {
+                self.transitions
+                    .iter()
+                    .min_by_key(|t| t.start)
+                    .map(|t| t.offset_from)
+            })
+            .unwrap_or(FixedOffset::east_opt(0).unwrap())
+    }
+}
+
+/// The instant of wall time `dt` in `tz`. Times repeated when the clocks go
+/// back are the first of the two, times skipped when they go forward use the
+/// offset from before the change (RFC 5545 3.3.5).
+fn wall_to_utc<T: TimeZone>(tz: &T, dt: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&dt) {
+        LocalResult::Single(x) | LocalResult::Ambiguous(x, _) => x.to_utc(),
+        LocalResult::None => {
+            // Offsets never change twice within a day
+            let before = tz.offset_from_utc_datetime(&(dt - TimeDelta::days(1)));
+            (dt - before.fix()).and_utc()
+        }
+    }
+}
+
+/// The zone a DATE-TIME value is expressed in.
+#[derive(Debug, Clone, Copy)]
+pub enum Zone<'a> {
+    /// No TZID and no trailing `Z`: local time of whoever reads the calendar.
+    Floating,
+    Utc,
+    Iana(Tz),
+    Embedded(&'a VTimezone),
+}
+
+impl<'a> Zone<'a> {
+    /// Picks the zone for a property from its TZID parameter (if any) and
+    /// whether its value carried a trailing `Z`.
+    pub fn resolve(tzid: Option<&str>, utc: bool, zones: &'a Timezones) -> Zone<'a> {
+        if utc {
+            return Zone::Utc;
+        }
+        let Some(tzid) = tzid.map(|x| x.trim_matches('"')) else {
+            return Zone::Floating;
+        };
+        if let Ok(tz) = tzid.parse::<Tz>() {
+            return Zone::Iana(tz);
+        }
+        if let Some(zone) = zones.get(tzid) {
+            return Zone::Embedded(zone);
+        }
+        // Some producers prefix the IANA name, e.g. `/mozilla.org/20050126_1/America/New_York`
+        let mut parts = tzid.rsplit('/');
+        if let (Some(city), Some(region)) = (parts.next(), parts.next()) {
+            if let Ok(tz) = format!("{}/{}", region, city).parse::<Tz>() {
+                return Zone::Iana(tz);
+            }
+        }
+        eprintln!("Unknown TZID `{}`, treating as local time", tzid);
+        Zone::Floating
+    }
+
+    pub fn to_utc(self, dt: NaiveDateTime) -> DateTime<Utc> {
+        match self {
+            Zone::Floating => wall_to_utc(&Local, dt),
+            Zone::Utc => dt.and_utc(),
+            Zone::Iana(tz) => wall_to_utc(&tz, dt),
+            Zone::Embedded(zone) => (dt - zone.offset(dt, false)).and_utc(),
+        }
+    }
+
+    /// Wall time in this zone at the instant `dt`.
+    pub fn wall_time(self, dt: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Zone::Floating => dt.with_timezone(&Local).naive_local(),
+            Zone::Utc => dt.naive_utc(),
+            Zone::Iana(tz) => dt.with_timezone(&tz).naive_local(),
+            Zone::Embedded(zone) => dt.naive_utc() + zone.offset(dt.naive_utc(), true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ical::IcalParser;
+    use std::io::BufReader;
+
+    const OUTLOOK: &str = "BEGIN:VCALENDAR
+BEGIN:VTIMEZONE
+TZID:Eastern Standard Time
+BEGIN:STANDARD
+DTSTART:16010101T020000
+TZOFFSETFROM:-0400
+TZOFFSETTO:-0500
+RRULE:FREQ=YEARLY;INTERVAL=1;BYDAY=1SU;BYMONTH=11
+END:STANDARD
+BEGIN:DAYLIGHT
+DTSTART:16010101T020000
+TZOFFSETFROM:-0500
+TZOFFSETTO:-0400
+RRULE:FREQ=YEARLY;INTERVAL=1;BYDAY=2SU;BYMONTH=3
+END:DAYLIGHT
+END:VTIMEZONE
+END:VCALENDAR
+";
+
+    fn dt(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%S").unwrap()
+    }
+
+    #[test]
+    fn embedded_vtimezone_follows_dst() {
+        let calendar = IcalParser::new(BufReader::new(OUTLOOK.as_bytes()))
+            .next()
+            .unwrap()
+            .unwrap();
+        let zones = parse_vtimezones(calendar.timezones);
+        let zone = Zone::resolve(Some("Eastern Standard Time"), false, &zones);
+        assert!(matches!(zone, Zone::Embedded(_)));
+
+        let winter = zone.to_utc(dt("20260102T103400"));
+        assert_eq!(winter.naive_utc(), dt("20260102T153400"));
+        let summer = zone.to_utc(dt("20260706T093000"));
+        assert_eq!(summer.naive_utc(), dt("20260706T133000"));
+        assert_eq!(zone.wall_time(summer), dt("20260706T093000"));
+        // First instant after the switch back to standard time
+        assert_eq!(
+            zone.wall_time(dt("20261101T060000").and_utc()),
+            dt("20261101T010000")
+        );
+    }
+
+    #[test]
+    fn iana_and_utc() {
+        let zones = Timezones::new();
+        let zone = Zone::resolve(Some("America/New_York"), false, &zones);
+        assert_eq!(
+            zone.to_utc(dt("20260102T103400")).naive_utc(),
+            dt("20260102T153400")
+        );
+        let zone = Zone::resolve(None, true, &zones);
+        assert_eq!(
+            zone.to_utc(dt("20260102T103400")).naive_utc(),
+            dt("20260102T103400")
+        );
+    }
+
+    #[test]
+    fn skipped_and_repeated_wall_times() {
+        let zones = Timezones::new();
+        let zone = Zone::resolve(Some("America/New_York"), false, &zones);
+        // 02:30 doesn't exist on the day DST starts, so it is read in EST
+        assert_eq!(
+            zone.to_utc(dt("20260308T023000")).naive_utc(),
+            dt("20260308T073000")
+        );
+        assert_eq!(
+            zone.wall_time(zone.to_utc(dt("20260308T023000"))),
+            dt("20260308T033000")
+        );
+        // 01:30 happens twice on the day it ends, the first time in EDT
+        assert_eq!(
+            zone.to_utc(dt("20261101T013000")).naive_utc(),
+            dt("20261101T053000")
+        );
+    }
+}
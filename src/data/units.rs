@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Celsius => "\u{B0}C",
+            Self::Fahrenheit => "\u{B0}F",
+        }
+    }
+
+    pub fn convert(self, value: f64, to: TemperatureUnit) -> f64 {
+        match (self, to) {
+            (Self::Celsius, Self::Fahrenheit) => value * 9.0 / 5.0 + 32.0,
+            (Self::Fahrenheit, Self::Celsius) => (value - 32.0) * 5.0 / 9.0,
+            _ => value,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpeedUnit {
+    #[default]
+    KilometresPerHour,
+    MilesPerHour,
+    MetresPerSecond,
+    Knots,
+}
+
+impl SpeedUnit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::KilometresPerHour => "km/h",
+            Self::MilesPerHour => "mph",
+            Self::MetresPerSecond => "m/s",
+            Self::Knots => "kn",
+        }
+    }
+
+    /// How many km/h one of this unit is
+    fn in_kmh(self) -> f64 {
+        match self {
+            Self::KilometresPerHour => 1.0,
+            Self::MilesPerHour => 1.609344,
+            Self::MetresPerSecond => 3.6,
+            Self::Knots => 1.852,
+        }
+    }
+
+    pub fn convert(self, value: f64, to: SpeedUnit) -> f64 {
+        if self == to {
+            return value;
+        }
+        value * self.in_kmh() / to.in_kmh()
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownUnit(pub String);
+impl std::fmt::Display for UnknownUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown unit `{}`", self.0)
+    }
+}
+impl std::error::Error for UnknownUnit {}
+
+impl FromStr for TemperatureUnit {
+    type Err = UnknownUnit;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().trim_start_matches('\u{B0}') {
+            "c" | "celsius" => Ok(Self::Celsius),
+            "f" | "fahrenheit" => Ok(Self::Fahrenheit),
+            _ => Err(UnknownUnit(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for SpeedUnit {
+    type Err = UnknownUnit;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "km/h" | "kmh" | "kph" => Ok(Self::KilometresPerHour),
+            "mph" => Ok(Self::MilesPerHour),
+            "m/s" | "ms" => Ok(Self::MetresPerSecond),
+            "kn" | "kt" | "knots" => Ok(Self::Knots),
+            _ => Err(UnknownUnit(s.to_string())),
+        }
+    }
+}
+
+/// The units a set of values is in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Units {
+    pub temperature: TemperatureUnit,
+    pub speed: SpeedUnit,
+}
+
+/// The units to show values in. `None` keeps what the provider sent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UnitPreference {
+    pub temperature: Option<TemperatureUnit>,
+    pub speed: Option<SpeedUnit>,
+}
+
+impl UnitPreference {
+    /// The units values in `from` should be converted to
+    pub fn resolve(self, from: Units) -> Units {
+        Units {
+            temperature: self.temperature.unwrap_or(from.temperature),
+            speed: self.speed.unwrap_or(from.speed),
+        }
+    }
+}
+
+/// `metric` or `imperial`
+impl FromStr for UnitPreference {
+    type Err = UnknownUnit;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (temperature, speed) = match s.trim().to_lowercase().as_str() {
+            "metric" => (TemperatureUnit::Celsius, SpeedUnit::KilometresPerHour),
+            "imperial" => (TemperatureUnit::Fahrenheit, SpeedUnit::MilesPerHour),
+            _ => return Err(UnknownUnit(s.to_string())),
+        };
+        Ok(UnitPreference {
+            temperature: Some(temperature),
+            speed: Some(speed),
+        })
+    }
+}
+
+const COMPASS: [&str; 16] = [
+    "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW", "NW",
+    "NNW",
+];
+
+/// The 16 point compass direction of a bearing in degrees, e.g. `SW`
+pub fn compass(degrees: f64) -> &'static str {
+    COMPASS[(degrees.rem_euclid(360.0) / 22.5).round() as usize % 16]
+}
+
+/// The bearing of a compass direction such as `SW`, as NWS forecasts give them
+pub fn bearing(compass: &str) -> Option<f64> {
+    COMPASS
+        .iter()
+        .position(|x| *x == compass)
+        .map(|i| i as f64 * 22.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let f = TemperatureUnit::Celsius.convert(-40.0, TemperatureUnit::Fahrenheit);
+        assert_eq!(f, -40.0);
+        let c = TemperatureUnit::Fahrenheit.convert(212.0, TemperatureUnit::Celsius);
+        assert_eq!(c, 100.0);
+
+        let near = |a: f64, b: f64| assert!((a - b).abs() < 0.001, "{} != {}", a, b);
+        near(
+            SpeedUnit::MilesPerHour.convert(10.0, SpeedUnit::KilometresPerHour),
+            16.093,
+        );
+        near(
+            SpeedUnit::MetresPerSecond.convert(10.0, SpeedUnit::Knots),
+            19.438,
+        );
+        near(
+            SpeedUnit::Knots.convert(10.0, SpeedUnit::MilesPerHour),
+            11.508,
+        );
+    }
+
+    #[test]
+    fn preferences() {
+        let nws = Units {
+            temperature: TemperatureUnit::Fahrenheit,
+            speed: SpeedUnit::MilesPerHour,
+        };
+        assert_eq!(UnitPreference::default().resolve(nws), nws);
+
+        let metric: UnitPreference = "Metric".parse().unwrap();
+        assert_eq!(metric.resolve(nws), Units::default());
+
+        // A system with one quantity overridden
+        let custom = UnitPreference {
+            speed: Some("m/s".parse().unwrap()),
+            ..metric
+        };
+        assert_eq!(custom.resolve(nws).speed, SpeedUnit::MetresPerSecond);
+
+        assert!("kelvin".parse::<TemperatureUnit>().is_err());
+        assert_eq!(
+            "\u{B0}F".parse::<TemperatureUnit>().unwrap(),
+            nws.temperature
+        );
+    }
+}
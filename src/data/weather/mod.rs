@@ -0,0 +1,392 @@
+use crate::data::solar;
+use crate::data::units::{TemperatureUnit, Units};
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::{blocking::Client, header::RETRY_AFTER, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::Cell;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub use nws::{Nws, NWS_URL};
+pub use open_meteo::OpenMeteo;
+
+mod nws;
+mod open_meteo;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WeatherData {
+    pub icon: String,
+    /// Units of every temperature and speed in here, as the provider sent them
+    #[serde(default)]
+    pub units: Units,
+    pub temperature: Option<f64>,
+    /// Feels like, allowing for wind chill or humidity
+    #[serde(default)]
+    pub apparent_temperature: Option<f64>,
+    #[serde(default)]
+    pub dew_point: Option<f64>,
+    /// Relative humidity in percent
+    #[serde(default)]
+    pub humidity: Option<f64>,
+    #[serde(default)]
+    pub wind_speed: Option<f64>,
+    /// Where the wind comes from, in degrees
+    #[serde(default)]
+    pub wind_direction: Option<f64>,
+    /// Chance of precipitation in percent, for the next hour or so
+    #[serde(default)]
+    pub precip_chance: Option<u8>,
+    /// Short description of the current conditions, e.g. `Mostly Cloudy`
+    #[serde(default)]
+    pub summary: String,
+    /// The coming days, starting today
+    #[serde(default)]
+    pub forecast: Vec<DailyForecast>,
+    /// The next [`HOURS`] hours, starting with the current one
+    #[serde(default)]
+    pub hourly: Vec<HourlyForecast>,
+    /// Active watches, warnings and advisories, most severe first
+    #[serde(default)]
+    pub alerts: Vec<WeatherAlert>,
+    pub time: DateTime<Utc>,
+}
+
+impl WeatherData {
+    /// Converts every temperature and speed to `to`. The forecast temperatures
+    /// are whole numbers, so they are rounded again after converting.
+    pub fn convert(&mut self, to: Units) {
+        let from = self.units;
+        if from == to {
+            return;
+        }
+        let temp = |x: f64| from.temperature.convert(x, to.temperature);
+        let whole = |x: i32| temp(x as f64).round() as i32;
+
+        self.temperature = self.temperature.map(temp);
+        self.apparent_temperature = self.apparent_temperature.map(temp);
+        self.dew_point = self.dew_point.map(temp);
+        self.wind_speed = self.wind_speed.map(|x| from.speed.convert(x, to.speed));
+        for day in &mut self.forecast {
+            day.high = day.high.map(whole);
+            day.low = day.low.map(whole);
+        }
+        for hour in &mut self.hourly {
+            hour.temperature = whole(hour.temperature);
+        }
+        self.units = to;
+    }
+}
+
+/// One day of the forecast. Either half may be missing, e.g. today has no
+/// high once the forecast has moved on to tonight.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyForecast {
+    pub date: NaiveDate,
+    pub high: Option<i32>,
+    pub low: Option<i32>,
+    pub icon: String,
+    pub summary: String,
+    /// Highest chance of precipitation over the day and night, in percent
+    pub precip_chance: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HourlyForecast {
+    pub time: DateTime<Utc>,
+    pub temperature: i32,
+    /// In percent
+    pub precip_chance: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Unknown,
+    Minor,
+    Moderate,
+    Severe,
+    Extreme,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeatherAlert {
+    /// e.g. `Tornado Warning`
+    pub event: String,
+    pub severity: Severity,
+    /// When the hazard is expected to end, or failing that when the alert does
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// How many hours of the hourly forecast are kept
+pub const HOURS: usize = 24;
+
+#[derive(Debug)]
+pub enum WeatherError {
+    NoResults,
+    /// The request could not be made or the response not read
+    Http(reqwest::Error),
+    /// A response other than 2xx, after any retries
+    Status {
+        url: String,
+        status: u16,
+    },
+    RateLimited {
+        url: String,
+        retry_after: Option<Duration>,
+    },
+    /// The body is not JSON at all
+    BadJson {
+        url: String,
+        error: String,
+    },
+    /// The JSON is missing a field we need or has it with the wrong type
+    MissingField(&'static str),
+    /// A URL built from the settings or from a response does not parse
+    BadUrl {
+        url: String,
+        error: String,
+    },
+}
+impl std::fmt::Display for WeatherError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoResults => write!(f, "No results from api"),
+            Self::Http(e) => write!(f, "Request failed: {}", e),
+            Self::Status { url, status } => write!(f, "HTTP {} from {}", status, url),
+            Self::RateLimited {
+                url,
+                retry_after: Some(after),
+            } => write!(f, "Rate limited by {}, retry after {:?}", url, after),
+            Self::RateLimited { url, .. } => write!(f, "Rate limited by {}", url),
+            Self::BadJson { url, error } => write!(f, "Bad JSON from {}: {}", url, error),
+            Self::MissingField(field) => write!(f, "Response has no `{}`", field),
+            Self::BadUrl { url, error } => write!(f, "Bad URL `{}`: {}", url, error),
+        }
+    }
+}
+impl std::error::Error for WeatherError {}
+
+/// Parses `url`, keeping it for the error if it doesn't
+fn parse_url(url: &str) -> Result<Url, WeatherError> {
+    Url::parse(url).map_err(|e| WeatherError::BadUrl {
+        url: url.to_string(),
+        error: e.to_string(),
+    })
+}
+
+/// Same as [`Url::parse_with_params`], with a [`WeatherError`]
+fn parse_url_with_params<'a>(
+    url: &str,
+    params: impl IntoIterator<Item = (&'a str, String)>,
+) -> Result<Url, WeatherError> {
+    Url::parse_with_params(url, params).map_err(|e| WeatherError::BadUrl {
+        url: url.to_string(),
+        error: e.to_string(),
+    })
+}
+
+/// The requests of one update, which share [`RETRY_BUDGET`]
+struct Session {
+    client: Client,
+    /// How much longer retries may wait
+    budget: Cell<Duration>,
+}
+
+/// A session for one update's weather requests
+fn client() -> Result<Session, WeatherError> {
+    let client = reqwest::blocking::ClientBuilder::new()
+        .user_agent(crate::data::http::USER_AGENT)
+        .build()
+        .map_err(WeatherError::Http)?;
+    Ok(Session {
+        client,
+        budget: Cell::new(RETRY_BUDGET),
+    })
+}
+
+/// How often a rate limited or failing (5xx) request is retried
+const RETRIES: u32 = 3;
+/// The first wait between retries, doubled each time unless the server sends
+/// `Retry-After`
+#[cfg(not(test))]
+const BACKOFF: Duration = Duration::from_secs(2);
+#[cfg(test)]
+const BACKOFF: Duration = Duration::from_millis(1);
+/// The most an update spends waiting to retry, in total. Past that the error
+/// is returned and the next run tries again once the TTL is up, rather than
+/// holding up the display.
+const RETRY_BUDGET: Duration = Duration::from_secs(5);
+
+/// GETs `url` and parses the body as JSON, retrying rate limits and server
+/// errors with backoff while the session's retry budget lasts.
+fn get_json(session: &Session, url: Url) -> Result<Value, WeatherError> {
+    let mut attempt = 0;
+    loop {
+        let resp = session
+            .client
+            .get(url.clone())
+            .send()
+            .map_err(WeatherError::Http)?;
+        let status = resp.status();
+        // Only the delay-seconds form, NWS does not send dates
+        let retry_after = resp
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.trim().parse().ok())
+            .map(Duration::from_secs);
+
+        let delay = retry_after.unwrap_or(BACKOFF * 2u32.pow(attempt));
+        if (status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+            && attempt < RETRIES
+            && delay <= session.budget.get()
+        {
+            eprintln!("{} from {}, retrying in {:?}", status, url, delay);
+            std::thread::sleep(delay);
+            session.budget.set(session.budget.get() - delay);
+            attempt += 1;
+            continue;
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(WeatherError::RateLimited {
+                url: url.to_string(),
+                retry_after,
+            });
+        }
+        if !status.is_success() {
+            return Err(WeatherError::Status {
+                url: url.to_string(),
+                status: status.as_u16(),
+            });
+        }
+        let text = resp.text().map_err(WeatherError::Http)?;
+        return serde_json::from_str(&text).map_err(|e| WeatherError::BadJson {
+            url: url.to_string(),
+            error: e.to_string(),
+        });
+    }
+}
+
+/// Maps an NWS condition code (e.g. `skc`, `tsra`) to a glyph in `FONT_EMOJI`.
+/// Other providers translate their own codes to these.
+fn mkicon(code: &str, is_daytime: bool) -> &'static str {
+    match code {
+        // Clear / clouds
+        "skc" if is_daytime => "\u{2600}",
+        "skc" => "\u{1F319}",
+
+        "few" if is_daytime => "\u{1F324}",
+        "few" => "\u{1F325}",
+
+        "sct" if is_daytime => "\u{26C5}",
+        "sct" => "\u{1F325}",
+
+        "bkn" if is_daytime => "\u{1F325}",
+        "bkn" => "\u{2601}",
+
+        "ovc" => "\u{2601}",
+
+        // Wind
+        "wind_skc" | "wind_few" | "wind_sct" | "wind_bkn" | "wind_ovc" => "\u{1F32C}",
+
+        // Snow / ice
+        "snow" => "\u{1F328}",
+        "blizzard" => "\u{2744}",
+        "fzra" => "\u{1F327}",
+        "rain_fzra" | "snow_fzra" => "\u{1F327}",
+
+        // Mixed precip
+        "rain_snow" | "rain_sleet" | "snow_sleet" | "sleet" => "\u{1F326}",
+
+        // Rain
+        "rain_showers" | "rain" if is_daytime => "\u{1F327}",
+        "rain_showers" | "rain" => "\u{1F327}",
+
+        "rain_showers_hi" if is_daytime => "\u{1F326}",
+        "rain_showers_hi" => "\u{1F326}",
+
+        // Thunder
+        "tsra" => "\u{26C8}",
+        "tsra_sct" | "tsra_hi" => "\u{26A1}",
+
+        // Severe
+        "tornado" | "hurricane" | "tropical_storm" => "\u{1F32A}",
+
+        // Atmosphere
+        "dust" => "\u{1F4A8}",
+        "smoke" => "\u{1F525}",
+        "haze" => "\u{1F301}",
+        "fog" => "\u{1F32B}",
+
+        // Temperature
+        "hot" => "\u{1F525}",
+        "cold" => "\u{1F9CA}",
+
+        _ => "",
+    }
+}
+
+/// Whether it is light out, i.e. between the start and end of civil twilight
+pub fn is_sun_up(lat_deg: f64, lon_deg: f64, now: DateTime<Utc>) -> bool {
+    solar::elevation(lat_deg, lon_deg, now) > -6.0
+}
+
+/// How warm it feels in °F: the NWS wind chill below 50°F with some wind, the
+/// heat index above 80°F and the temperature itself in between.
+fn apparent_temperature(temp: f64, humidity: Option<f64>, wind_mph: Option<f64>) -> f64 {
+    match (humidity, wind_mph) {
+        (_, Some(wind)) if temp <= 50.0 && wind > 3.0 => {
+            let v = wind.powf(0.16);
+            35.74 + 0.6215 * temp - 35.75 * v + 0.4275 * temp * v
+        }
+        (Some(rh), _) if temp >= 80.0 => {
+            // Rothfusz regression
+            -42.379 + 2.04901523 * temp + 10.14333127 * rh
+                - 0.22475541 * temp * rh
+                - 0.00683783 * temp * temp
+                - 0.05481717 * rh * rh
+                + 0.00122874 * temp * temp * rh
+                + 0.00085282 * temp * rh * rh
+                - 0.00000199 * temp * temp * rh * rh
+        }
+        _ => temp,
+    }
+}
+
+/// [`apparent_temperature`] for values in `units`
+fn feels_like(units: Units, temp: f64, humidity: Option<f64>, wind: Option<f64>) -> f64 {
+    let fahrenheit = units.temperature.convert(temp, TemperatureUnit::Fahrenheit);
+    let mph = wind.map(|x| {
+        units
+            .speed
+            .convert(x, crate::data::units::SpeedUnit::MilesPerHour)
+    });
+    TemperatureUnit::Fahrenheit.convert(
+        apparent_temperature(fahrenheit, humidity, mph),
+        units.temperature,
+    )
+}
+
+/// A source of current conditions.
+pub trait WeatherProvider: std::fmt::Debug {
+    fn fetch(&self, lat: f64, lon: f64) -> Result<WeatherData, WeatherError>;
+}
+
+/// Picks a provider by name, as given in `WEATHER_PROVIDER`. Providers may
+/// keep lookups that rarely change in `state_dir`.
+pub fn mkprovider(name: &str, state_dir: Option<PathBuf>) -> Option<Box<dyn WeatherProvider>> {
+    match name {
+        "nws" => Some(Box::new(Nws::new(NWS_URL.to_string(), state_dir))),
+        "open-meteo" => Some(Box::new(OpenMeteo::default())),
+        _ => None,
+    }
+}
+
+pub fn mkweather(
+    provider: &dyn WeatherProvider,
+    lat: f64,
+    lon: f64,
+) -> Result<WeatherData, WeatherError> {
+    provider.fetch(lat, lon)
+}
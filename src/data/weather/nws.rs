@@ -0,0 +1,650 @@
+use super::{
+    client, feels_like, get_json, is_sun_up, mkicon, parse_url, parse_url_with_params,
+    DailyForecast, HourlyForecast, Session, Severity, WeatherAlert, WeatherData, WeatherError,
+    WeatherProvider, HOURS,
+};
+use crate::data::state;
+use crate::data::units::{bearing, SpeedUnit, TemperatureUnit, Units};
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::path::PathBuf;
+
+pub const NWS_URL: &str = "https://api.weather.gov";
+
+/// The US National Weather Service API at api.weather.gov.
+#[derive(Debug)]
+pub struct Nws {
+    pub base_url: String,
+    /// Where the gridpoint is kept between runs
+    state_dir: Option<PathBuf>,
+    gridpoint: RefCell<Option<Gridpoint>>,
+}
+
+/// The forecast URLs `/points` maps a location to. They only change if the
+/// NWS redraws its grid, which shows as a 404 on the forecast.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Gridpoint {
+    lat: f64,
+    lon: f64,
+    forecast: String,
+    forecast_hourly: Option<String>,
+    alerts: String,
+    /// Latest observation of the nearest station
+    #[serde(default)]
+    observations: Option<String>,
+}
+
+impl Nws {
+    pub fn new(base_url: String, state_dir: Option<PathBuf>) -> Self {
+        let gridpoint = state_dir
+            .as_ref()
+            .and_then(|dir| state::load::<Option<Gridpoint>>(dir, state::GRIDPOINT_FILE));
+        Nws {
+            base_url,
+            state_dir,
+            gridpoint: RefCell::new(gridpoint),
+        }
+    }
+
+    /// Looks up the forecast URLs for a location and remembers them.
+    fn resolve(&self, client: &Session, lat: f64, lon: f64) -> Result<Gridpoint, WeatherError> {
+        let url = parse_url(&format!("{}/points/{},{}", self.base_url, lat, lon))?;
+        let properties = &get_json(client, url)?["properties"];
+        let gridpoint = Gridpoint {
+            lat,
+            lon,
+            forecast: properties["forecast"]
+                .as_str()
+                .ok_or(WeatherError::MissingField("properties.forecast"))?
+                .to_string(),
+            forecast_hourly: properties["forecastHourly"].as_str().map(|x| x.to_string()),
+            alerts: parse_url_with_params(
+                &format!("{}/alerts/active", self.base_url),
+                [("point", format!("{},{}", lat, lon))],
+            )?
+            .to_string(),
+            observations: properties["observationStations"]
+                .as_str()
+                .and_then(|url| get_optional(client, url, "observation stations"))
+                .and_then(|x| x["features"][0]["id"].as_str().map(|x| x.to_string()))
+                .map(|station| format!("{}/observations/latest", station)),
+        };
+
+        if let Some(dir) = &self.state_dir {
+            if let Err(e) = state::save(dir, state::GRIDPOINT_FILE, &Some(&gridpoint)) {
+                eprintln!("Failed to save gridpoint: {}", e);
+            }
+        }
+        *self.gridpoint.borrow_mut() = Some(gridpoint.clone());
+        Ok(gridpoint)
+    }
+}
+
+impl Default for Nws {
+    fn default() -> Self {
+        Nws::new(NWS_URL.to_string(), None)
+    }
+}
+
+/// GETs something we can do without, so a failure only gets logged.
+fn get_optional(client: &Session, url: &str, what: &str) -> Option<Value> {
+    let result = match Url::parse(url) {
+        Ok(url) => get_json(client, url).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    result
+        .inspect_err(|e| eprintln!("Failed to get {}: {}", what, e))
+        .ok()
+}
+
+/// The value of a quantity such as `{"unitCode": "wmoUnit:degC", "value": 9.4}`
+/// in °C or km/h, as NWS gives observations in either.
+fn quantity(value: &Value) -> Option<f64> {
+    let x = value["value"].as_f64()?;
+    match value["unitCode"].as_str() {
+        Some("wmoUnit:degF") => {
+            Some(TemperatureUnit::Fahrenheit.convert(x, TemperatureUnit::Celsius))
+        }
+        Some("wmoUnit:m_s-1") => Some(x * 3.6),
+        _ => Some(x),
+    }
+}
+
+/// The highest speed in a forecast wind such as `5 to 10 mph`
+fn wind_speed(text: &str) -> Option<f64> {
+    text.split_whitespace()
+        .filter_map(|x| x.parse::<f64>().ok())
+        .reduce(f64::max)
+}
+
+/// The condition code in an icon url such as
+/// `https://api.weather.gov/icons/land/day/rain,40?size=medium`
+fn icon_code(icon_url: &str) -> &str {
+    icon_url
+        .split('/')
+        .next_back()
+        .unwrap()
+        .split('?')
+        .take(1)
+        .collect::<Vec<_>>()[0]
+        .split(',')
+        .take(1)
+        .collect::<Vec<_>>()[0]
+}
+
+/// Pairs the day and night periods of a forecast into days. The night of a
+/// period belongs to the date it starts on.
+fn daily(periods: &[Value]) -> Vec<DailyForecast> {
+    let mut days: Vec<DailyForecast> = Vec::new();
+    for period in periods {
+        let Some(date) = period["startTime"]
+            .as_str()
+            .and_then(|x| DateTime::parse_from_rfc3339(x).ok())
+            .map(|x| x.date_naive())
+        else {
+            continue;
+        };
+        let is_daytime = period["isDaytime"].as_bool().unwrap_or(true);
+        let temperature = period["temperature"].as_i64().map(|x| x as i32);
+        let icon = mkicon(icon_code(period["icon"].as_str().unwrap_or("")), is_daytime);
+        let summary = period["shortForecast"].as_str().unwrap_or("");
+        let precip_chance = period["probabilityOfPrecipitation"]["value"]
+            .as_u64()
+            .map(|x| x.min(100) as u8);
+
+        let day = match days.last_mut() {
+            Some(day) if day.date == date => day,
+            _ => {
+                days.push(DailyForecast {
+                    date,
+                    high: None,
+                    low: None,
+                    icon: icon.to_string(),
+                    summary: summary.to_string(),
+                    precip_chance: None,
+                });
+                days.last_mut().unwrap()
+            }
+        };
+        if is_daytime {
+            // The day's icon and text describe it better than the night's
+            day.high = temperature;
+            day.icon = icon.to_string();
+            day.summary = summary.to_string();
+        } else {
+            day.low = temperature;
+        }
+        day.precip_chance = day.precip_chance.max(precip_chance);
+    }
+    days
+}
+
+fn hourly(periods: &[Value]) -> Vec<HourlyForecast> {
+    periods
+        .iter()
+        .filter_map(|period| {
+            Some(HourlyForecast {
+                time: DateTime::parse_from_rfc3339(period["startTime"].as_str()?)
+                    .ok()?
+                    .to_utc(),
+                temperature: period["temperature"].as_i64()? as i32,
+                precip_chance: period["probabilityOfPrecipitation"]["value"]
+                    .as_u64()
+                    .map(|x| x.min(100) as u8),
+            })
+        })
+        .take(HOURS)
+        .collect()
+}
+
+/// The features of an `/alerts/active` response, most severe first.
+fn alerts(features: &[Value]) -> Vec<WeatherAlert> {
+    let mut alerts: Vec<WeatherAlert> = features
+        .iter()
+        .map(|feature| &feature["properties"])
+        .filter_map(|alert| {
+            Some(WeatherAlert {
+                event: alert["event"].as_str()?.to_string(),
+                severity: match alert["severity"].as_str() {
+                    Some("Extreme") => Severity::Extreme,
+                    Some("Severe") => Severity::Severe,
+                    Some("Moderate") => Severity::Moderate,
+                    Some("Minor") => Severity::Minor,
+                    _ => Severity::Unknown,
+                },
+                expires: alert["ends"]
+                    .as_str()
+                    .or(alert["expires"].as_str())
+                    .and_then(|x| DateTime::parse_from_rfc3339(x).ok())
+                    .map(|x| x.to_utc()),
+            })
+        })
+        .collect();
+    alerts.sort_by_key(|x| std::cmp::Reverse(x.severity));
+    alerts
+}
+
+impl WeatherProvider for Nws {
+    fn fetch(&self, lat: f64, lon: f64) -> Result<WeatherData, WeatherError> {
+        let client = client()?;
+        let cached = self
+            .gridpoint
+            .borrow()
+            .clone()
+            .filter(|x| x.lat == lat && x.lon == lon);
+        let (gridpoint, forecast_resp) = match cached {
+            Some(gridpoint) => match get_json(&client, parse_url(&gridpoint.forecast)?) {
+                Err(WeatherError::Status { status: 404, .. }) => {
+                    eprintln!("Forecast moved, looking up the gridpoint again");
+                    let gridpoint = self.resolve(&client, lat, lon)?;
+                    let resp = get_json(&client, parse_url(&gridpoint.forecast)?)?;
+                    (gridpoint, resp)
+                }
+                resp => (gridpoint, resp?),
+            },
+            None => {
+                let gridpoint = self.resolve(&client, lat, lon)?;
+                let resp = get_json(&client, parse_url(&gridpoint.forecast)?)?;
+                (gridpoint, resp)
+            }
+        };
+        let periods = forecast_resp["properties"]["periods"]
+            .as_array()
+            .ok_or(WeatherError::MissingField("properties.periods"))?;
+        if periods.is_empty() {
+            return Err(WeatherError::NoResults);
+        }
+        let period = &periods[0];
+        let temp = period["temperature"]
+            .as_f64()
+            .ok_or(WeatherError::MissingField("temperature"))?;
+        let units = Units {
+            temperature: match period["temperatureUnit"].as_str() {
+                Some("F") => TemperatureUnit::Fahrenheit,
+                Some("C") => TemperatureUnit::Celsius,
+                _ => return Err(WeatherError::MissingField("temperatureUnit")),
+            },
+            speed: match period["windSpeed"].as_str() {
+                Some(x) if x.ends_with("km/h") => SpeedUnit::KilometresPerHour,
+                _ => SpeedUnit::MilesPerHour,
+            },
+        };
+        let icon = period["icon"].as_str().unwrap_or("");
+        let summary = period["shortForecast"].as_str().unwrap_or("");
+        let is_daytime = is_sun_up(lat, lon, Utc::now());
+
+        // Nice to have, so failures here do not lose the rest of the forecast
+        let hourly_resp = gridpoint
+            .forecast_hourly
+            .as_ref()
+            .and_then(|url| get_optional(&client, url, "hourly forecast"))
+            .unwrap_or_default();
+        let this_hour = &hourly_resp["properties"]["periods"][0];
+        let alerts = get_optional(&client, &gridpoint.alerts, "weather alerts")
+            .map(|x| alerts(x["features"].as_array().unwrap_or(&Vec::new())))
+            .unwrap_or_default();
+        // Stations sometimes stop reporting, so skip old observations
+        let observation = gridpoint
+            .observations
+            .as_ref()
+            .and_then(|url| get_optional(&client, url, "latest observation"))
+            .map(|x| x["properties"].clone())
+            .filter(|x| {
+                x["timestamp"]
+                    .as_str()
+                    .and_then(|x| DateTime::parse_from_rfc3339(x).ok())
+                    .is_some_and(|x| Utc::now() - x.to_utc() < TimeDelta::hours(3))
+            })
+            .unwrap_or_default();
+
+        // Observations are metric, prefer them over the forecast when there
+        let celsius = |x: f64| TemperatureUnit::Celsius.convert(x, units.temperature);
+        let kmh = |x: f64| SpeedUnit::KilometresPerHour.convert(x, units.speed);
+        let temperature = quantity(&observation["temperature"])
+            .map(celsius)
+            .unwrap_or(temp);
+        let humidity =
+            quantity(&observation["relativeHumidity"]).or(quantity(&this_hour["relativeHumidity"]));
+        let dew_point = quantity(&observation["dewpoint"])
+            .or(quantity(&this_hour["dewpoint"]))
+            .map(celsius);
+        let (wind_speed, wind_direction) = match quantity(&observation["windSpeed"]) {
+            Some(speed) => (Some(kmh(speed)), quantity(&observation["windDirection"])),
+            None => (
+                period["windSpeed"].as_str().and_then(wind_speed),
+                period["windDirection"].as_str().and_then(bearing),
+            ),
+        };
+        let apparent_temperature = quantity(&observation["windChill"])
+            .or(quantity(&observation["heatIndex"]))
+            .map(celsius)
+            .unwrap_or_else(|| feels_like(units, temperature, humidity, wind_speed));
+        let precip_chance = period["probabilityOfPrecipitation"]["value"]
+            .as_u64()
+            .or(this_hour["probabilityOfPrecipitation"]["value"].as_u64())
+            .map(|x| x.min(100) as u8);
+
+        Ok(WeatherData {
+            icon: mkicon(icon_code(icon), is_daytime).to_string(),
+            units,
+            temperature: Some(temperature),
+            apparent_temperature: Some(apparent_temperature),
+            dew_point,
+            humidity,
+            wind_speed,
+            wind_direction,
+            precip_chance,
+            summary: summary.to_string(),
+            forecast: daily(periods),
+            hourly: hourly(
+                hourly_resp["properties"]["periods"]
+                    .as_array()
+                    .unwrap_or(&Vec::new()),
+            ),
+            alerts,
+            time: Utc::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::mock;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn forecast_from_fixtures() {
+        let base_url = mock::serve(vec![
+            (
+                "/points/42.3297,-83.0425",
+                200,
+                mock::fixture("nws_points.json"),
+            ),
+            (
+                "/gridpoints/DTX/66,34/forecast",
+                200,
+                mock::fixture("nws_forecast.json"),
+            ),
+            (
+                "/gridpoints/DTX/66,34/forecast/hourly",
+                200,
+                mock::fixture("nws_hourly.json"),
+            ),
+            ("/alerts/active", 200, mock::fixture("nws_alerts.json")),
+            (
+                "/gridpoints/DTX/66,34/stations",
+                200,
+                mock::fixture("nws_stations.json"),
+            ),
+            (
+                "/stations/KDET/observations/latest",
+                200,
+                mock::fixture("nws_observation.json"),
+            ),
+        ]);
+        let weather = Nws::new(base_url, None).fetch(42.3297, -83.0425).unwrap();
+        assert_eq!(weather.icon, "\u{2601}");
+        assert_eq!(weather.summary, "Cloudy");
+
+        // Current conditions come from the observation, converted from metric
+        let near = |actual: Option<f64>, expected: f64| {
+            let actual = actual.unwrap();
+            assert!(
+                (actual - expected).abs() < 0.01,
+                "{} != {}",
+                actual,
+                expected
+            );
+        };
+        assert_eq!(weather.units.temperature, TemperatureUnit::Fahrenheit);
+        assert_eq!(weather.units.speed, SpeedUnit::MilesPerHour);
+        near(weather.temperature, 48.92);
+        near(weather.apparent_temperature, 46.17);
+        near(weather.dew_point, 42.98);
+        near(weather.humidity, 79.82);
+        near(weather.wind_speed, 6.93);
+        near(weather.wind_direction, 230.0);
+        // Tonight has no chance of precipitation, so it is this hour's
+        assert_eq!(weather.precip_chance, Some(2));
+
+        // Tonight, then Sunday through Saturday
+        assert_eq!(weather.forecast.len(), 8);
+        assert_eq!(
+            weather.forecast[0],
+            DailyForecast {
+                date: NaiveDate::from_ymd_opt(2026, 10, 17).unwrap(),
+                high: None,
+                low: Some(48),
+                icon: "\u{2601}".to_string(),
+                summary: "Cloudy".to_string(),
+                precip_chance: None,
+            }
+        );
+        assert_eq!(
+            weather.forecast[2],
+            DailyForecast {
+                date: NaiveDate::from_ymd_opt(2026, 10, 19).unwrap(),
+                high: Some(58),
+                low: Some(41),
+                icon: "\u{1F327}".to_string(),
+                summary: "Rain Likely".to_string(),
+                precip_chance: Some(60),
+            }
+        );
+        let last = weather.forecast.last().unwrap();
+        assert_eq!((last.high, last.low), (Some(52), None));
+
+        assert_eq!(
+            weather.alerts,
+            [
+                WeatherAlert {
+                    event: "Tornado Warning".to_string(),
+                    severity: Severity::Extreme,
+                    expires: Some("2026-10-18T03:30:00Z".parse().unwrap()),
+                },
+                WeatherAlert {
+                    event: "Wind Advisory".to_string(),
+                    severity: Severity::Moderate,
+                    expires: Some("2026-10-18T12:00:00Z".parse().unwrap()),
+                },
+            ]
+        );
+
+        assert_eq!(weather.hourly.len(), HOURS);
+        assert_eq!(
+            weather.hourly[19],
+            HourlyForecast {
+                time: "2026-10-18T16:00:00Z".parse().unwrap(),
+                temperature: 60,
+                precip_chance: Some(30),
+            }
+        );
+
+        // Shown in metric, as set by UNITS=metric
+        let mut weather = weather;
+        weather.convert(Units::default());
+        near(weather.temperature, 9.4);
+        near(weather.wind_speed, 11.16);
+        assert_eq!(weather.forecast[2].high, Some(14));
+        assert_eq!(weather.forecast[2].low, Some(5));
+        assert_eq!(weather.hourly[19].temperature, 16);
+    }
+
+    fn fetch_error(routes: Vec<(&str, u16, String)>) -> WeatherError {
+        let base_url = mock::serve(routes);
+        Nws::new(base_url, None)
+            .fetch(42.3297, -83.0425)
+            .unwrap_err()
+    }
+
+    #[test]
+    fn long_retry_after_is_not_waited_out() {
+        let (base_url, requests) = mock::serve_routes(vec![mock::Route {
+            headers: vec![("Retry-After".to_string(), "3600".to_string())],
+            ..("/points/42.3297,-83.0425", 429, "{}".to_string()).into()
+        }]);
+        let start = std::time::Instant::now();
+        let error = Nws::new(base_url, None)
+            .fetch(42.3297, -83.0425)
+            .unwrap_err();
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+        assert!(
+            matches!(
+                error,
+                WeatherError::RateLimited {
+                    retry_after: Some(after),
+                    ..
+                } if after.as_secs() == 3600
+            ),
+            "{error}"
+        );
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn bad_base_url() {
+        let error = Nws::new("not a url".to_string(), None)
+            .fetch(42.3297, -83.0425)
+            .unwrap_err();
+        assert!(
+            matches!(&error, WeatherError::BadUrl { url, .. } if url.starts_with("not a url")),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        let base_url = mock::serve(vec![
+            ("/points/42.3297,-83.0425", 503, "{}".to_string()),
+            ("/points/42.3297,-83.0425", 502, "{}".to_string()),
+            (
+                "/points/42.3297,-83.0425",
+                200,
+                mock::fixture("nws_points.json"),
+            ),
+            (
+                "/gridpoints/DTX/66,34/forecast",
+                200,
+                mock::fixture("nws_forecast.json"),
+            ),
+        ]);
+        let weather = Nws::new(base_url, None).fetch(42.3297, -83.0425).unwrap();
+
+        // Without an observation the current conditions come from the forecast
+        assert_eq!(weather.temperature, Some(48.0));
+        assert_eq!(weather.wind_speed, Some(5.0));
+        assert_eq!(weather.wind_direction, Some(225.0));
+        let wind_chill = weather.apparent_temperature.unwrap();
+        assert!((wind_chill - 45.87).abs() < 0.01, "{}", wind_chill);
+    }
+
+    #[test]
+    fn errors() {
+        let error = fetch_error(vec![("/points/42.3297,-83.0425", 429, "{}".to_string())]);
+        assert!(matches!(error, WeatherError::RateLimited { .. }), "{error}");
+
+        let error = fetch_error(vec![("/points/42.3297,-83.0425", 500, "{}".to_string())]);
+        assert!(
+            matches!(error, WeatherError::Status { status: 500, .. }),
+            "{error}"
+        );
+
+        let error = fetch_error(vec![(
+            "/points/42.3297,-83.0425",
+            200,
+            "<html>".to_string(),
+        )]);
+        assert!(matches!(error, WeatherError::BadJson { .. }), "{error}");
+
+        let error = fetch_error(vec![(
+            "/points/42.3297,-83.0425",
+            200,
+            r#"{"properties": {}}"#.to_string(),
+        )]);
+        assert!(
+            matches!(error, WeatherError::MissingField("properties.forecast")),
+            "{error}"
+        );
+
+        let mut forecast: Value =
+            serde_json::from_str(&mock::fixture("nws_forecast.json")).unwrap();
+        forecast["properties"]["periods"][0]["temperature"] = Value::Null;
+        let error = fetch_error(vec![
+            (
+                "/points/42.3297,-83.0425",
+                200,
+                mock::fixture("nws_points.json"),
+            ),
+            ("/gridpoints/DTX/66,34/forecast", 200, forecast.to_string()),
+        ]);
+        assert!(
+            matches!(error, WeatherError::MissingField("temperature")),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn caches_gridpoint() {
+        let base_url = mock::serve(vec![
+            (
+                "/points/42.3297,-83.0425",
+                200,
+                mock::fixture("nws_points.json"),
+            ),
+            // Only the first lookup may reach /points
+            ("/points/42.3297,-83.0425", 500, "{}".to_string()),
+            (
+                "/gridpoints/DTX/66,34/forecast",
+                200,
+                mock::fixture("nws_forecast.json"),
+            ),
+        ]);
+        let nws = Nws::new(base_url, None);
+        nws.fetch(42.3297, -83.0425).unwrap();
+        nws.fetch(42.3297, -83.0425).unwrap();
+
+        // A different location is looked up again
+        assert!(nws.fetch(42.0, -83.0).is_err());
+    }
+
+    #[test]
+    fn resolves_again_on_404() {
+        let dir = std::env::temp_dir().join(format!("nws-gridpoint-{}", std::process::id()));
+        let base_url = mock::serve(vec![
+            (
+                "/points/42.3297,-83.0425",
+                200,
+                mock::fixture("nws_points.json"),
+            ),
+            (
+                "/gridpoints/DTX/66,34/forecast",
+                200,
+                mock::fixture("nws_forecast.json"),
+            ),
+        ]);
+        let moved = Gridpoint {
+            lat: 42.3297,
+            lon: -83.0425,
+            forecast: format!("{}/gridpoints/DTX/65,33/forecast", base_url),
+            forecast_hourly: None,
+            alerts: format!("{}/alerts/active", base_url),
+            observations: None,
+        };
+        state::save(&dir, state::GRIDPOINT_FILE, &Some(&moved)).unwrap();
+
+        let weather = Nws::new(base_url.clone(), Some(dir.clone()))
+            .fetch(42.3297, -83.0425)
+            .unwrap();
+        assert_eq!(weather.temperature, Some(48.0));
+        let saved: Option<Gridpoint> = state::load(&dir, state::GRIDPOINT_FILE);
+        assert_eq!(
+            saved.unwrap().forecast,
+            format!("{}/gridpoints/DTX/66,34/forecast", base_url)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
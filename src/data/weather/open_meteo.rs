@@ -0,0 +1,230 @@
+use super::{
+    client, get_json, is_sun_up, mkicon, parse_url_with_params, DailyForecast, HourlyForecast,
+    WeatherData, WeatherError, WeatherProvider, HOURS,
+};
+use crate::data::units::Units;
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde_json::Value;
+
+/// The Open-Meteo forecast API, which covers the whole world.
+#[derive(Debug)]
+pub struct OpenMeteo {
+    pub base_url: String,
+}
+
+impl Default for OpenMeteo {
+    fn default() -> Self {
+        OpenMeteo {
+            base_url: "https://api.open-meteo.com".to_string(),
+        }
+    }
+}
+
+/// Translates a WMO weather interpretation code into the matching NWS code.
+fn nws_code(wmo: i64) -> &'static str {
+    match wmo {
+        0 => "skc",
+        1 => "few",
+        2 => "sct",
+        3 => "ovc",
+        45 | 48 => "fog",
+        51 | 53 | 55 | 61 | 63 | 65 => "rain",
+        56 | 57 | 66 | 67 => "fzra",
+        71 | 73 | 75 | 77 | 85 | 86 => "snow",
+        80..=82 => "rain_showers",
+        95 | 96 | 99 => "tsra",
+        _ => "",
+    }
+}
+
+/// A short description of a WMO weather code in the style of the NWS
+/// `shortForecast`.
+fn describe(wmo: i64) -> &'static str {
+    match wmo {
+        0 => "Clear",
+        1 => "Mostly Clear",
+        2 => "Partly Cloudy",
+        3 => "Cloudy",
+        45 | 48 => "Fog",
+        51 | 53 | 55 => "Drizzle",
+        56 | 57 => "Freezing Drizzle",
+        61 | 63 | 65 => "Rain",
+        66 | 67 => "Freezing Rain",
+        71 | 73 | 75 => "Snow",
+        77 => "Snow Grains",
+        80..=82 => "Rain Showers",
+        85 | 86 => "Snow Showers",
+        95 => "Thunderstorms",
+        96 | 99 => "Thunderstorms And Hail",
+        _ => "",
+    }
+}
+
+/// Zips the parallel arrays of the `daily` block into days.
+fn daily(daily: &Value) -> Vec<DailyForecast> {
+    let Some(dates) = daily["time"].as_array() else {
+        return Vec::new();
+    };
+    dates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, date)| {
+            let date = NaiveDate::parse_from_str(date.as_str()?, "%Y-%m-%d").ok()?;
+            let code = daily["weather_code"][i].as_i64().unwrap_or(-1);
+            Some(DailyForecast {
+                date,
+                high: daily["temperature_2m_max"][i]
+                    .as_f64()
+                    .map(|x| x.round() as i32),
+                low: daily["temperature_2m_min"][i]
+                    .as_f64()
+                    .map(|x| x.round() as i32),
+                icon: mkicon(nws_code(code), true).to_string(),
+                summary: describe(code).to_string(),
+                precip_chance: daily["precipitation_probability_max"][i]
+                    .as_u64()
+                    .map(|x| x.min(100) as u8),
+            })
+        })
+        .collect()
+}
+
+/// The `hourly` block has local times, `offset` is the location's UTC offset
+/// in seconds.
+fn hourly(hourly: &Value, offset: i32) -> Vec<HourlyForecast> {
+    let Some(times) = hourly["time"].as_array() else {
+        return Vec::new();
+    };
+    let Some(offset) = FixedOffset::east_opt(offset) else {
+        return Vec::new();
+    };
+    times
+        .iter()
+        .enumerate()
+        .filter_map(|(i, time)| {
+            let time = NaiveDateTime::parse_from_str(time.as_str()?, "%Y-%m-%dT%H:%M").ok()?;
+            Some(HourlyForecast {
+                time: offset.from_local_datetime(&time).single()?.to_utc(),
+                temperature: hourly["temperature_2m"][i].as_f64()?.round() as i32,
+                precip_chance: hourly["precipitation_probability"][i]
+                    .as_u64()
+                    .map(|x| x.min(100) as u8),
+            })
+        })
+        .take(HOURS)
+        .collect()
+}
+
+impl WeatherProvider for OpenMeteo {
+    fn fetch(&self, lat: f64, lon: f64) -> Result<WeatherData, WeatherError> {
+        let client = client()?;
+        let url = parse_url_with_params(
+            &format!("{}/v1/forecast", self.base_url),
+            [
+                ("latitude", lat.to_string()),
+                ("longitude", lon.to_string()),
+                (
+                    "current",
+                    "temperature_2m,relative_humidity_2m,apparent_temperature,dew_point_2m,\
+                     weather_code,wind_speed_10m,wind_direction_10m"
+                        .to_string(),
+                ),
+                (
+                    "daily",
+                    "weather_code,temperature_2m_max,temperature_2m_min,precipitation_probability_max"
+                        .to_string(),
+                ),
+                (
+                    "hourly",
+                    "temperature_2m,precipitation_probability".to_string(),
+                ),
+                ("forecast_hours", HOURS.to_string()),
+                ("timezone", "auto".to_string()),
+            ],
+        )?;
+        let resp = get_json(&client, url)?;
+
+        let current = &resp["current"];
+        let temp = current["temperature_2m"]
+            .as_f64()
+            .ok_or(WeatherError::MissingField("current.temperature_2m"))?;
+        let code = current["weather_code"].as_i64().unwrap_or(-1);
+        let is_daytime = is_sun_up(lat, lon, Utc::now());
+
+        Ok(WeatherData {
+            icon: mkicon(nws_code(code), is_daytime).to_string(),
+            // Open-Meteo defaults to metric
+            units: Units::default(),
+            temperature: Some(temp),
+            apparent_temperature: current["apparent_temperature"].as_f64(),
+            dew_point: current["dew_point_2m"].as_f64(),
+            humidity: current["relative_humidity_2m"].as_f64(),
+            wind_speed: current["wind_speed_10m"].as_f64(),
+            wind_direction: current["wind_direction_10m"].as_f64(),
+            // There is no current chance of precipitation, use this hour's
+            precip_chance: resp["hourly"]["precipitation_probability"][0]
+                .as_u64()
+                .map(|x| x.min(100) as u8),
+            summary: describe(code).to_string(),
+            forecast: daily(&resp["daily"]),
+            // Open-Meteo has no warnings
+            alerts: Vec::new(),
+            hourly: hourly(
+                &resp["hourly"],
+                resp["utc_offset_seconds"].as_i64().unwrap_or(0) as i32,
+            ),
+            time: Utc::now(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::mock;
+
+    #[test]
+    fn current_from_fixture() {
+        let base_url = mock::serve(vec![(
+            "/v1/forecast",
+            200,
+            mock::fixture("open_meteo_forecast.json"),
+        )]);
+        let weather = OpenMeteo { base_url }.fetch(52.52, 13.41).unwrap();
+        assert_eq!(weather.icon, "\u{2601}");
+        assert_eq!(weather.units, Units::default());
+        assert_eq!(weather.temperature, Some(11.3));
+        assert_eq!(weather.apparent_temperature, Some(9.6));
+        assert_eq!(weather.dew_point, Some(8.3));
+        assert_eq!(weather.humidity, Some(82.0));
+        assert_eq!(weather.wind_speed, Some(9.4));
+        assert_eq!(weather.wind_direction, Some(245.0));
+        assert_eq!(weather.precip_chance, Some(0));
+        assert_eq!(weather.summary, "Cloudy");
+
+        assert_eq!(weather.forecast.len(), 7);
+        assert_eq!(
+            weather.forecast[2],
+            DailyForecast {
+                date: NaiveDate::from_ymd_opt(2026, 10, 19).unwrap(),
+                high: Some(12),
+                low: Some(8),
+                icon: "\u{1F327}".to_string(),
+                summary: "Rain".to_string(),
+                precip_chance: Some(75),
+            }
+        );
+        assert_eq!(weather.forecast[6].precip_chance, None);
+
+        assert_eq!(weather.hourly.len(), HOURS);
+        assert_eq!(
+            weather.hourly[18],
+            HourlyForecast {
+                time: "2026-10-18T14:00:00Z".parse().unwrap(),
+                temperature: 12,
+                precip_chance: Some(10),
+            }
+        );
+        assert_eq!(weather.hourly[23].precip_chance, None);
+    }
+}
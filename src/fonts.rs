@@ -1,15 +1,56 @@
 use embedded_graphics::prelude::*;
-use epd_waveshare::color::Color;
+use epd_waveshare::color::TriColor;
 
 include!(concat!(env!("OUT_DIR"), "/fonts.rs"));
 
 use crate::image_gen::Disp;
 
+/// The fonts a layout is drawn with, by role
+#[derive(Debug)]
+pub struct FontSet {
+    pub header: &'static Font,
+    pub body: &'static Font,
+    pub small: &'static Font,
+    pub large: &'static Font,
+    pub emoji: &'static Font,
+    pub emoji_small: &'static Font,
+}
+
+pub static REGULAR: FontSet = FontSet {
+    header: &FONT_HEADER,
+    body: &FONT_BODY,
+    small: &FONT_SMALL,
+    large: &FONT_LARGE,
+    emoji: &FONT_EMOJI,
+    emoji_small: &FONT_EMOJI_SMALL,
+};
+
+/// For panels smaller than 800x480
+pub static COMPACT: FontSet = FontSet {
+    header: &COMPACT_HEADER,
+    body: &COMPACT_BODY,
+    small: &COMPACT_SMALL,
+    large: &COMPACT_LARGE,
+    emoji: &COMPACT_EMOJI,
+    emoji_small: &COMPACT_EMOJI_SMALL,
+};
+
 pub fn draw_text(
     display: &mut Disp,
     font: &Font,
     text: &str,
     origin: Point, // baseline origin
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_text_in(display, font, text, origin, TriColor::White)
+}
+
+/// [`draw_text`] in `color`, e.g. black on an inverted banner
+pub fn draw_text_in(
+    display: &mut Disp,
+    font: &Font,
+    text: &str,
+    origin: Point, // baseline origin
+    color: TriColor,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut cursor_x = origin.x;
     let baseline_y = origin.y;
@@ -31,11 +72,8 @@ pub fn draw_text(
                 let bit = 7 - (bit_index & 7);
 
                 if (byte >> bit) & 1 != 0 {
-                    Pixel(
-                        Point::new(glyph_x + x as i32, glyph_y + y as i32),
-                        Color::White,
-                    )
-                    .draw(display)?;
+                    Pixel(Point::new(glyph_x + x as i32, glyph_y + y as i32), color)
+                        .draw(display)?;
                 }
 
                 bit_index += 1;
@@ -1,133 +1,786 @@
 use crate::data::calendar::CalendarEvent;
+use crate::data::units::compass;
+use crate::data::weather::{DailyForecast, WeatherAlert};
 use crate::{data::DisplayData, fonts::*};
+use chrono::{DateTime, Datelike, Local, TimeDelta, Timelike, Utc, Weekday};
 use embedded_graphics::{prelude::*, primitives::*};
-use epd_waveshare::color::Color;
+use epd_waveshare::color::TriColor;
 
-pub type Disp = epd_waveshare::epd7in5_v2::Display7in5;
+pub type Disp = crate::panel::Frame;
 
-pub const WIDTH: i32 = 800;
-pub const HEIGHT: i32 = 480;
-const TOP: i32 = 50;
-const LEFT_COL_X: i32 = 15;
-const LEFT_COL_W: i32 = 350;
-const RIGHT_COL_X: i32 = LEFT_COL_X + LEFT_COL_W;
 const LINE_GAP: i32 = 4;
 const TITLE_LINES_MAX: i32 = 2;
+const ALERTS_MAX: usize = 2;
 
-const LINE_HEIGHT: i32 = (FONT_BODY.ascent - FONT_BODY.descent) as i32;
-const DETAIL_BLOCK_HEIGHT: i32 = LINE_HEIGHT + LINE_GAP;
-const BOTTOM_LIMIT: i32 = HEIGHT - (LINE_GAP * 2);
+/// Where things go on a panel of a given size, and in which fonts
+struct Layout {
+    width: i32,
+    height: i32,
+    fonts: &'static FontSet,
+    /// Whether the panel has a third color to highlight things in
+    has_color: bool,
+    top: i32,
+    left_x: i32,
+    left_w: i32,
+    right_x: i32,
+    line_height: i32,
+    bottom: i32,
+    forecast_cell_w: i32,
+}
+
+impl Layout {
+    fn new(display: &Disp) -> Self {
+        let size = display.size();
+        let (width, height) = (size.width as i32, size.height as i32);
+        let (fonts, top, left_x) = if width >= 800 && height >= 480 {
+            (&REGULAR, 50, 15)
+        } else {
+            (&COMPACT, 30, 8)
+        };
+        // The date and time need about half of a narrow panel
+        let left_w = if width < 600 {
+            width / 2
+        } else {
+            width * 7 / 16
+        };
+        Layout {
+            width,
+            height,
+            fonts,
+            has_color: display.has_color(),
+            top,
+            left_x,
+            left_w,
+            right_x: left_x + left_w,
+            line_height: (fonts.body.ascent - fonts.body.descent) as i32,
+            bottom: height - LINE_GAP * 2,
+            forecast_cell_w: left_w / 5,
+        }
+    }
 
+    /// Where the clock showing `time` goes if drawn at `baseline`, as wide as
+    /// the text so a partial refresh doesn't take in what is next to it
+    fn clock(&self, baseline: i32, time: &str) -> Rectangle {
+        let top = baseline - self.fonts.large.ascent as i32;
+        Rectangle::new(
+            Point::new(self.left_x, top),
+            Size::new(
+                text_width(self.fonts.large, time) as u32,
+                (baseline - self.fonts.large.descent as i32 - top) as u32,
+            ),
+        )
+    }
+}
+
+/// Draws `data` and returns where the clock is, to refresh on its own while
+/// nothing else changes
 pub fn create_image(
     display: &mut Disp,
     data: &DisplayData,
-) -> Result<(), Box<dyn std::error::Error>> {
-    Rectangle::new(Point::new(0, 0), Size::new(WIDTH as u32, HEIGHT as u32))
-        .into_styled(PrimitiveStyle::with_fill(Color::Black))
+) -> Result<Rectangle, Box<dyn std::error::Error>> {
+    let l = &Layout::new(display);
+    Rectangle::new(Point::new(0, 0), Size::new(l.width as u32, l.height as u32))
+        .into_styled(PrimitiveStyle::with_fill(TriColor::Black))
         .draw(display)?;
+    if l.height < 200 {
+        return draw_small(display, l, data);
+    }
+
+    // Alerts take priority and push everything else down
+    let alerts: Vec<&WeatherAlert> = data
+        .weather
+        .alerts
+        .iter()
+        .filter(|x| x.expires.is_none_or(|expires| expires > data.now))
+        .collect();
+    let banner_h = draw_alerts(display, l, &alerts, data.now)?;
 
     // Left column
-    let mut y = (TOP as f32 * 1.5) as i32;
+    let mut y = ((l.top as f32 * 1.5) as i32).max(banner_h + l.fonts.header.ascent as i32);
 
     // Date + Time
-    draw_text(display, &FONT_HEADER, data.date.as_str(), Point::new(LEFT_COL_X, y))?;
-    y += 2 * (FONT_HEADER.ascent - FONT_HEADER.descent) as i32;
-    draw_text(display, &FONT_LARGE, data.time.as_str(), Point::new(LEFT_COL_X, y))?;
-    y += 2 * (FONT_LARGE.ascent - FONT_LARGE.descent) as i32;
+    draw_highlighted(
+        display,
+        l,
+        l.fonts.header,
+        data.date.as_str(),
+        Point::new(l.left_x, y),
+    )?;
+    y += (l.fonts.large.ascent - l.fonts.header.descent) as i32 + LINE_GAP * 4;
+    let clock = l.clock(y, &data.time);
+    draw_text(
+        display,
+        l.fonts.large,
+        data.time.as_str(),
+        Point::new(l.left_x, y),
+    )?;
+    y += (l.fonts.emoji.ascent - l.fonts.large.descent) as i32 + LINE_GAP * 6;
 
     // Weather
-    let icon_glyph = FONT_EMOJI
+    let icon_glyph = l
+        .fonts
+        .emoji
         .glyphs
         .iter()
         .find(|(c, _)| data.weather.icon.starts_with(*c));
-    if let Some((_, glyph)) = icon_glyph {
-        draw_text(
-            display,
-            &FONT_EMOJI,
-            data.weather.icon,
-            Point::new(LEFT_COL_X, y),
-        )?;
+    let temperature = temperature(data);
+    let temperature_x = match icon_glyph {
+        Some((_, glyph)) => {
+            draw_text(
+                display,
+                l.fonts.emoji,
+                data.weather.icon.as_str(),
+                Point::new(l.left_x, y),
+            )?;
+            l.left_x + glyph.width as i32 * 2
+        }
+        None => l.left_x,
+    };
+    draw_text(
+        display,
+        l.fonts.header,
+        temperature.as_str(),
+        Point::new(temperature_x, y),
+    )?;
+
+    // Hourly chart in the rest of the row
+    let chart_x = temperature_x + text_width(l.fonts.header, temperature.as_str()) + LINE_GAP * 4;
+    let chart = Rectangle::with_corners(
+        Point::new(chart_x, y - l.fonts.header.ascent as i32),
+        Point::new(l.right_x - LINE_GAP * 2, y),
+    );
+    draw_hourly(display, l, data, chart)?;
+
+    if !data.weather.summary.is_empty() {
+        y += (l.fonts.body.ascent - l.fonts.header.descent) as i32 + LINE_GAP;
         draw_text(
             display,
-            &FONT_HEADER,
-            data.weather.temperature.as_str(),
-            Point::new(LEFT_COL_X + glyph.width as i32 * 2, y),
+            l.fonts.body,
+            data.weather.summary.as_str(),
+            Point::new(l.left_x, y),
         )?;
-    } else {
+    }
+
+    // Details, as many to a line as fit on up to two lines
+    let mut line = String::new();
+    let mut lines = Vec::new();
+    for part in conditions(data) {
+        let joined = format!("{}  {}", line, part);
+        if line.is_empty() {
+            line = part;
+        } else if text_width(l.fonts.small, &joined) > l.left_w - LINE_GAP {
+            lines.push(std::mem::replace(&mut line, part));
+        } else {
+            line = joined;
+        }
+    }
+    lines.extend((!line.is_empty()).then_some(line));
+    for (i, line) in lines.iter().take(2).enumerate() {
+        y += if i == 0 {
+            (l.fonts.small.ascent - l.fonts.body.descent) as i32 + LINE_GAP
+        } else {
+            (l.fonts.small.ascent - l.fonts.small.descent) as i32
+        };
+        draw_text(display, l.fonts.small, line, Point::new(l.left_x, y))?;
+    }
+
+    // Forecast strip
+    y += -l.fonts.small.descent as i32 + LINE_GAP * 2;
+    draw_forecast(display, l, data, Point::new(l.left_x, y))?;
+
+    // Warnings about data that could not be refreshed, bottom up
+    let mut y = l.bottom;
+    let calendars = data.calendar.feeds.iter().map(|feed| {
+        let name = match &feed.label {
+            Some(label) => format!("Calendar {}", label),
+            None => "Calendar".to_string(),
+        };
+        (name, &feed.status)
+    });
+    for (name, status) in calendars.chain([("Weather".to_string(), &data.weather_status)]) {
+        if !status.stale {
+            continue;
+        }
+        let text = match status.last_success {
+            Some(time) => format!(
+                "{} updated {} ago",
+                name,
+                format_age(data.now.to_utc() - time)
+            ),
+            None => format!("{} unavailable", name),
+        };
+        let width = draw_warning_sign(display, l, Point::new(l.left_x, y), TriColor::White)?;
         draw_text(
             display,
-            &FONT_HEADER,
-            data.weather.temperature.as_str(),
-            Point::new(LEFT_COL_X, y),
+            l.fonts.body,
+            text.as_str(),
+            Point::new(l.left_x + width + LINE_GAP * 2, y),
         )?;
+        y -= l.line_height + LINE_GAP;
     }
 
     // Right Column
-    let mut y = TOP;
+    let mut y = l.top.max(banner_h + l.fonts.body.ascent as i32);
+
+    // Sun and moon along the bottom
+    draw_moon(display, l, data, Point::new(l.right_x, l.bottom))?;
+    draw_sun(
+        display,
+        l,
+        data,
+        Point::new(l.right_x, l.bottom - l.line_height - LINE_GAP),
+    )?;
+    let calendar_limit = l.bottom - (l.line_height + LINE_GAP) * 2 - LINE_GAP;
 
     // Calendar
     for event in &data.calendar.events {
+        let title = match &event.label {
+            Some(label) => format!("{} {}", label, event.title),
+            None => event.title.clone(),
+        };
         let title_lines = wrap_text(
-            &FONT_BODY,
-            event.title.as_str(),
-            (WIDTH - LEFT_COL_W) as i16,
+            l.fonts.body,
+            title.as_str(),
+            (l.width - l.left_w) as i16,
             TITLE_LINES_MAX as usize,
         );
 
-        if y + title_lines.len() as i32 * (FONT_BODY.ascent - FONT_BODY.descent) as i32
-            + DETAIL_BLOCK_HEIGHT
-            > BOTTOM_LIMIT
+        if y + title_lines.len() as i32 * (l.fonts.body.ascent - l.fonts.body.descent) as i32
+            + (l.line_height + LINE_GAP)
+            > calendar_limit
         {
             break;
         }
 
         for line in title_lines {
-            draw_text(display, &FONT_BODY, line, Point::new(RIGHT_COL_X, y))?;
-            y += FONT_BODY.pixel_size as i32 + LINE_GAP;
+            let origin = Point::new(l.right_x, y);
+            if event.allday {
+                draw_highlighted(display, l, l.fonts.body, line, origin)?;
+            } else {
+                draw_text(display, l.fonts.body, line, origin)?;
+            }
+            y += l.fonts.body.pixel_size as i32 + LINE_GAP;
         }
 
+        // Narrow panels only have room for when a long event starts
+        let when = event_when(event, data.now);
+        let when = match when.split_once(" - ") {
+            Some((start, _)) if text_width(l.fonts.body, &when) > l.width - l.right_x => start,
+            _ => when.as_str(),
+        };
+        draw_text(display, l.fonts.body, when, Point::new(l.right_x, y))?;
+        y += (l.fonts.body.ascent - l.fonts.body.descent) as i32 + LINE_GAP;
+    }
+
+    Ok(clock)
+}
+
+/// Everything in one block for panels too short for the two columns: the time
+/// and date, the weather to the right of them and the next event along the
+/// bottom.
+fn draw_small(
+    display: &mut Disp,
+    l: &Layout,
+    data: &DisplayData,
+) -> Result<Rectangle, Box<dyn std::error::Error>> {
+    let fonts = l.fonts;
+    let top_y = LINE_GAP * 2 + fonts.large.ascent as i32;
+    let second_y = top_y + (fonts.body.ascent - fonts.large.descent) as i32 + LINE_GAP;
+    draw_text(
+        display,
+        fonts.large,
+        &data.time,
+        Point::new(l.left_x, top_y),
+    )?;
+    draw_highlighted(
+        display,
+        l,
+        fonts.body,
+        &data.date,
+        Point::new(l.left_x, second_y),
+    )?;
+
+    let temperature = temperature(data);
+    let x = l.width - l.left_x - text_width(fonts.header, &temperature);
+    draw_text(display, fonts.header, &temperature, Point::new(x, top_y))?;
+    let mut x = l.right_x;
+    if let Some((_, glyph)) = fonts
+        .emoji_small
+        .glyphs
+        .iter()
+        .find(|(c, _)| data.weather.icon.starts_with(*c))
+    {
         draw_text(
             display,
-            &FONT_BODY,
-            match &event {
-                CalendarEvent {
-                    start,
-                    end: None,
-                    allday: true,
-                    title: _,
-                } => start.format("%a %b %d").to_string(),
-                CalendarEvent {
-                    start,
-                    end: Some(end),
-                    allday: true,
-                    title: _,
-                } => format!("{} - {}", start.format("%a %b %d"), end.format("%a %b %d")),
-                CalendarEvent {
-                    start,
-                    end: None,
-                    allday: false,
-                    title: _,
-                } => start.format("%a %b %d %-I %p").to_string(),
-                CalendarEvent {
-                    start,
-                    end: Some(end),
-                    allday: false,
-                    title: _,
-                } => format!(
-                    "{} - {}",
-                    start.format("%a %b %d %-I %p"),
-                    end.format("%a %b %d %-I %p")
-                ),
-            }
-            .as_str(),
-            Point::new(RIGHT_COL_X, y),
+            fonts.emoji_small,
+            &data.weather.icon,
+            Point::new(x, second_y),
+        )?;
+        x += glyph.x_advance as i32 + LINE_GAP;
+    }
+    draw_text(
+        display,
+        fonts.body,
+        &data.weather.summary,
+        Point::new(x, second_y),
+    )?;
+
+    if let Some(event) = data.calendar.events.first() {
+        let title = match &event.label {
+            Some(label) => format!("{} {}", label, event.title),
+            None => event.title.clone(),
+        };
+        let width = (l.width - l.left_x * 2) as i16;
+        let when_y = l.bottom;
+        let title_y = when_y - (fonts.small.ascent - fonts.small.descent) as i32;
+        if let Some(line) = wrap_text(fonts.small, &title, width, 1).first() {
+            draw_text(display, fonts.small, line, Point::new(l.left_x, title_y))?;
+        }
+        draw_text(
+            display,
+            fonts.small,
+            &event_when(event, data.now),
+            Point::new(l.left_x, when_y),
         )?;
-        y += (FONT_BODY.ascent - FONT_BODY.descent) as i32 + LINE_GAP;
     }
+    Ok(l.clock(top_y, &data.time))
+}
+
+/// The current temperature with its unit, e.g. `49°F`
+fn temperature(data: &DisplayData) -> String {
+    data.weather
+        .temperature
+        .map(|x| format!("{:.0}{}", x, data.weather.units.temperature.symbol()))
+        .unwrap_or_default()
+}
+
+/// Draws one column per forecast day below `top_left`: the day, its icon,
+/// high/low and chance of precipitation.
+fn draw_forecast(
+    display: &mut Disp,
+    l: &Layout,
+    data: &DisplayData,
+    top_left: Point,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Baselines of the rows in each column
+    let name_y = top_left.y + l.fonts.small.ascent as i32;
+    let icon_y = name_y + (l.fonts.emoji_small.ascent - l.fonts.small.descent) as i32 + LINE_GAP;
+    let temps_y = icon_y + (l.fonts.small.ascent - l.fonts.emoji_small.descent) as i32 + LINE_GAP;
+    let precip_y = temps_y + (l.fonts.small.ascent - l.fonts.small.descent) as i32;
+    // Alerts and details can leave too little room, then the chance of
+    // precipitation goes first and the whole strip after it
+    if temps_y > l.bottom {
+        return Ok(());
+    }
+
+    let days = data
+        .weather
+        .forecast
+        .iter()
+        .take((l.left_w / l.forecast_cell_w) as usize);
+    for (i, day) in days.enumerate() {
+        let center = top_left.x + i as i32 * l.forecast_cell_w + l.forecast_cell_w / 2;
+        let name = if day.date == data.now.date_naive() {
+            "Today".to_string()
+        } else {
+            day.date.format("%a").to_string()
+        };
+        let weekend = matches!(day.date.weekday(), Weekday::Sat | Weekday::Sun);
+        if weekend || day.date == data.now.date_naive() {
+            let x = center - text_width(l.fonts.small, &name) / 2;
+            draw_highlighted(display, l, l.fonts.small, &name, Point::new(x, name_y))?;
+        } else {
+            draw_centered(display, l.fonts.small, &name, center, name_y)?;
+        }
+        draw_centered(display, l.fonts.emoji_small, &day.icon, center, icon_y)?;
+        draw_centered(display, l.fonts.small, &high_low(day), center, temps_y)?;
+        if let Some(chance) = day.precip_chance.filter(|x| *x > 0 && precip_y <= l.bottom) {
+            draw_centered(
+                display,
+                l.fonts.small,
+                &format!("{}%", chance),
+                center,
+                precip_y,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Draws one line with sunrise, sunset and day length on the baseline at
+/// `origin`. In polar night it shows civil twilight instead, if there is any.
+fn draw_sun(
+    display: &mut Disp,
+    l: &Layout,
+    data: &DisplayData,
+    origin: Point,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sun = &data.sun;
+    let time = |x: DateTime<Utc>| x.with_timezone(&Local).format("%-I:%M %p").to_string();
+    let length = format!(
+        "{}h {}m",
+        sun.day_length.num_hours(),
+        sun.day_length.num_minutes() % 60
+    );
+    let parts = match (sun.sunrise, sun.sunset, sun.dawn, sun.dusk) {
+        (Some(rise), Some(set), _, _) => vec![
+            ("\u{1F305}", time(rise)),
+            ("\u{1F307}", time(set)),
+            ("", length),
+        ],
+        _ if sun.day_length > TimeDelta::zero() => vec![("\u{2600}", "Sun up all day".to_string())],
+        (_, _, Some(dawn), Some(dusk)) => vec![(
+            "\u{1F319}",
+            format!("Twilight {} - {}", time(dawn), time(dusk)),
+        )],
+        _ => vec![("\u{1F319}", "Sun down all day".to_string())],
+    };
+
+    let mut x = origin.x;
+    for (icon, text) in parts {
+        // Leave out what does not fit, the day length first
+        if x + text_width(l.fonts.body, &text) > l.width {
+            break;
+        }
+        if let Some((_, glyph)) = l
+            .fonts
+            .emoji_small
+            .glyphs
+            .iter()
+            .find(|(c, _)| icon.starts_with(*c))
+        {
+            draw_text(display, l.fonts.emoji_small, icon, Point::new(x, origin.y))?;
+            x += glyph.x_advance as i32 + LINE_GAP;
+        }
+        draw_text(display, l.fonts.body, &text, Point::new(x, origin.y))?;
+        x += text_width(l.fonts.body, &text) + LINE_GAP * 4;
+    }
+    Ok(())
+}
+
+/// Draws the moon's glyph, phase and illumination on the baseline at `origin`.
+fn draw_moon(
+    display: &mut Disp,
+    l: &Layout,
+    data: &DisplayData,
+    origin: Point,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let glyph = data.moon.glyph();
+    let mut x = origin.x;
+    if let Some((_, g)) = l
+        .fonts
+        .emoji_small
+        .glyphs
+        .iter()
+        .find(|(c, _)| glyph.starts_with(*c))
+    {
+        draw_text(display, l.fonts.emoji_small, glyph, Point::new(x, origin.y))?;
+        x += g.x_advance as i32 + LINE_GAP;
+    }
+    let text = format!(
+        "{}, {:.0}% lit",
+        data.moon.name(),
+        data.moon.illumination * 100.0
+    );
+    draw_text(display, l.fonts.body, &text, Point::new(x, origin.y))
+}
+
+/// The current conditions besides the temperature, e.g. `Wind SW 7 mph`, most
+/// useful first
+fn conditions(data: &DisplayData) -> Vec<String> {
+    let weather = &data.weather;
+    let degrees = |x: f64| format!("{:.0}\u{B0}", x);
+    let mut parts = Vec::new();
+    if let Some(x) = weather.apparent_temperature {
+        parts.push(format!("Feels {}", degrees(x)));
+    }
+    match (weather.wind_speed, weather.wind_direction) {
+        (Some(speed), _) if speed.round() == 0.0 => parts.push("Calm".to_string()),
+        (Some(speed), direction) => parts.push(format!(
+            "Wind {}{:.0} {}",
+            direction
+                .map(|x| format!("{} ", compass(x)))
+                .unwrap_or_default(),
+            speed,
+            weather.units.speed.symbol()
+        )),
+        _ => {}
+    }
+    if let Some(x) = weather.precip_chance {
+        parts.push(format!("Precip {}%", x));
+    }
+    if let Some(x) = weather.humidity {
+        parts.push(format!("Humidity {:.0}%", x));
+    }
+    if let Some(x) = weather.dew_point {
+        parts.push(format!("Dew point {}", degrees(x)));
+    }
+    parts
+}
+
+/// Draws the coming hours into `area`: temperature as a line in the upper part
+/// and chance of precipitation as bars below it, with the time every 6 hours
+/// underneath. Shows as many hours as fit at `HOUR_W` pixels each.
+fn draw_hourly(
+    display: &mut Disp,
+    l: &Layout,
+    data: &DisplayData,
+    area: Rectangle,
+) -> Result<(), Box<dyn std::error::Error>> {
+    const HOUR_W: i32 = 6;
+    // The forecast may be older than an hour, skip what is past
+    let hourly = &data.weather.hourly;
+    let past = hourly
+        .iter()
+        .take_while(|x| x.time + TimeDelta::hours(1) <= data.now)
+        .count();
+    let count = (area.size.width as i32 / HOUR_W) as usize;
+    let hours = &hourly[past..hourly.len().min(past + count)];
+    if hours.len() < 2 {
+        return Ok(());
+    }
+
+    let left = area.top_left.x;
+    let top = area.top_left.y;
+    let bottom = top + area.size.height as i32;
+    let bars_h = area.size.height as i32 / 3;
+    let line_bottom = bottom - bars_h - LINE_GAP;
+    let line_h = line_bottom - top;
+
+    let min = hours.iter().map(|x| x.temperature).min().unwrap_or(0);
+    let max = hours.iter().map(|x| x.temperature).max().unwrap_or(0);
+    let range = (max - min).max(1);
+    let points: Vec<Point> = hours
+        .iter()
+        .enumerate()
+        .map(|(i, hour)| {
+            Point::new(
+                left + i as i32 * HOUR_W + HOUR_W / 2,
+                line_bottom - (hour.temperature - min) * line_h / range,
+            )
+        })
+        .collect();
+    Polyline::new(&points)
+        .into_styled(PrimitiveStyle::with_stroke(TriColor::White, 2))
+        .draw(display)?;
+
+    for (i, hour) in hours.iter().enumerate() {
+        let x = left + i as i32 * HOUR_W;
+        let height = hour.precip_chance.unwrap_or(0) as i32 * bars_h / 100;
+        if height > 0 {
+            Rectangle::new(
+                Point::new(x, bottom - height),
+                Size::new(HOUR_W as u32 - 1, height as u32),
+            )
+            .into_styled(PrimitiveStyle::with_fill(TriColor::White))
+            .draw(display)?;
+        }
+
+        let local = hour.time.with_timezone(&Local);
+        if local.hour() % 6 == 0 {
+            Line::new(Point::new(x, bottom), Point::new(x, bottom + LINE_GAP))
+                .into_styled(PrimitiveStyle::with_stroke(TriColor::White, 1))
+                .draw(display)?;
+            draw_centered(
+                display,
+                l.fonts.small,
+                local.format("%-I%P").to_string().trim_end_matches('m'),
+                x,
+                bottom + LINE_GAP + l.fonts.small.ascent as i32,
+            )?;
+        }
+    }
+    Line::new(
+        Point::new(left, bottom),
+        Point::new(left + hours.len() as i32 * HOUR_W, bottom),
+    )
+    .into_styled(PrimitiveStyle::with_stroke(TriColor::White, 1))
+    .draw(display)?;
 
     Ok(())
 }
 
+/// `61/45`, with a dash for a missing half
+fn high_low(day: &DailyForecast) -> String {
+    let temp = |x: Option<i32>| x.map_or("-".to_string(), |x| x.to_string());
+    format!("{}/{}", temp(day.high), temp(day.low))
+}
+
+fn draw_centered(
+    display: &mut Disp,
+    font: &Font,
+    text: &str,
+    center_x: i32,
+    y: i32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let width = text_width(font, text);
+    draw_text(display, font, text, Point::new(center_x - width / 2, y))
+}
+
+/// Draws `text` so it stands out: in the panel's third color, or inverted on
+/// panels without one.
+fn draw_highlighted(
+    display: &mut Disp,
+    l: &Layout,
+    font: &Font,
+    text: &str,
+    origin: Point, // baseline origin
+) -> Result<(), Box<dyn std::error::Error>> {
+    if l.has_color {
+        return draw_text_in(display, font, text, origin, TriColor::Chromatic);
+    }
+    let pad = LINE_GAP / 2;
+    Rectangle::new(
+        Point::new(origin.x - pad, origin.y - font.ascent as i32 - pad),
+        Size::new(
+            (text_width(font, text) + pad * 2) as u32,
+            ((font.ascent - font.descent) as i32 + pad * 2) as u32,
+        ),
+    )
+    .into_styled(PrimitiveStyle::with_fill(TriColor::White))
+    .draw(display)?;
+    draw_text_in(display, font, text, origin, TriColor::Black)
+}
+
+fn text_width(font: &Font, text: &str) -> i32 {
+    text.chars()
+        .filter_map(|ch| font.glyphs.iter().find(|(c, _)| *c == ch))
+        .map(|(_, g)| g.x_advance as i32)
+        .sum()
+}
+
+/// Draws the most severe alerts in black on a white band across the top, one
+/// per line, and returns the height taken.
+fn draw_alerts(
+    display: &mut Disp,
+    l: &Layout,
+    alerts: &[&WeatherAlert],
+    now: DateTime<Local>,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    if alerts.is_empty() {
+        return Ok(0);
+    }
+    let shown = alerts.len().min(ALERTS_MAX);
+    let height = shown as i32 * (l.line_height + LINE_GAP) + LINE_GAP * 2;
+    let fill = if l.has_color {
+        TriColor::Chromatic
+    } else {
+        TriColor::White
+    };
+    Rectangle::new(Point::new(0, 0), Size::new(l.width as u32, height as u32))
+        .into_styled(PrimitiveStyle::with_fill(fill))
+        .draw(display)?;
+
+    let mut y = LINE_GAP + l.fonts.body.ascent as i32;
+    for (i, alert) in alerts.iter().take(shown).enumerate() {
+        let mut text = alert.event.clone();
+        if let Some(expires) = alert.expires {
+            let expires = expires.with_timezone(&Local);
+            let format = if expires.date_naive() == now.date_naive() {
+                "%-I:%M %p"
+            } else {
+                "%a %-I:%M %p"
+            };
+            text += &format!(" until {}", expires.format(format));
+        }
+        if i + 1 == shown && alerts.len() > shown {
+            text += &format!(" (+{} more)", alerts.len() - shown);
+        }
+        let width = draw_warning_sign(display, l, Point::new(l.left_x, y), TriColor::Black)?;
+        draw_text_in(
+            display,
+            l.fonts.body,
+            text.as_str(),
+            Point::new(l.left_x + width + LINE_GAP * 2, y),
+            TriColor::Black,
+        )?;
+        y += l.line_height + LINE_GAP;
+    }
+
+    Ok(height + LINE_GAP * 2)
+}
+
+/// Draws a small triangle with an exclamation mark on the baseline at `origin`
+/// and returns its width.
+fn draw_warning_sign(
+    display: &mut Disp,
+    l: &Layout,
+    origin: Point,
+    color: TriColor,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let size = l.fonts.body.ascent as i32;
+    Triangle::new(
+        Point::new(origin.x, origin.y),
+        Point::new(origin.x + size, origin.y),
+        Point::new(origin.x + size / 2, origin.y - size),
+    )
+    .into_styled(PrimitiveStyle::with_stroke(color, 2))
+    .draw(display)?;
+    let mark = l
+        .fonts
+        .body
+        .glyphs
+        .iter()
+        .find(|(c, _)| *c == '!')
+        .map(|(_, g)| g.x_advance as i32)
+        .unwrap_or(0);
+    draw_text_in(
+        display,
+        l.fonts.body,
+        "!",
+        Point::new(origin.x + (size - mark) / 2, origin.y - 1),
+        color,
+    )?;
+    Ok(size)
+}
+
+/// A short human readable age, e.g. `45m`, `3h` or `2d`
+fn format_age(age: TimeDelta) -> String {
+    if age.num_days() > 0 {
+        format!("{}d", age.num_days())
+    } else if age.num_hours() > 0 {
+        format!("{}h", age.num_hours())
+    } else {
+        format!("{}m", age.num_minutes().max(0))
+    }
+}
+
+/// The line under an event's title: when it happens, or until when it still runs
+fn event_when(event: &CalendarEvent, now: DateTime<Local>) -> String {
+    const DAY: &str = "%a %b %d";
+    const DAY_TIME: &str = "%a %b %d %-I %p";
+    let start = event.start;
+    let started_earlier = start.date_naive() < now.date_naive();
+
+    if event.allday {
+        // DTEND of an all-day event is the day after it ends
+        let last_day = event
+            .end
+            .and_then(|end| end.date_naive().pred_opt())
+            .filter(|last| *last > start.date_naive());
+        return match last_day {
+            Some(last) if started_earlier => format!("Continuing until {}", last.format(DAY)),
+            Some(last) => format!("{} - {}", start.format(DAY), last.format(DAY)),
+            None => start.format(DAY).to_string(),
+        };
+    }
+
+    match event.end {
+        Some(end) if start <= now && now < end && started_earlier => {
+            format!("Continuing until {}", end.format(DAY_TIME))
+        }
+        Some(end) if start <= now && now < end => {
+            if end.date_naive() == now.date_naive() {
+                format!("Now until {}", end.format("%-I %p"))
+            } else {
+                format!("Now until {}", end.format(DAY_TIME))
+            }
+        }
+        Some(end) => format!("{} - {}", start.format(DAY_TIME), end.format(DAY_TIME)),
+        None => start.format(DAY_TIME).to_string(),
+    }
+}
+
 fn wrap_text<'a>(font: &Font, text: &'a str, max_width: i16, max_lines: usize) -> Vec<&'a str> {
     let mut lines = Vec::new();
     let mut start = 0;
@@ -167,3 +820,104 @@ fn wrap_text<'a>(font: &Font, text: &'a str, max_width: i16, max_lines: usize) -
 
     lines
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 10, day, hour, 0, 0).unwrap()
+    }
+
+    fn event(start: DateTime<Local>, end: DateTime<Local>, allday: bool) -> CalendarEvent {
+        CalendarEvent {
+            title: "Event".to_string(),
+            start,
+            end: Some(end),
+            allday,
+            label: None,
+            uid: None,
+            recurrence_id: None,
+        }
+    }
+
+    #[test]
+    fn running_events() {
+        let now = at(17, 10);
+        assert_eq!(
+            event_when(&event(at(17, 9), at(17, 11), false), now),
+            "Now until 11 AM"
+        );
+        assert_eq!(
+            event_when(&event(at(17, 9), at(18, 10), false), now),
+            "Now until Sun Oct 18 10 AM"
+        );
+        assert_eq!(
+            event_when(&event(at(16, 20), at(17, 12), false), now),
+            "Continuing until Sat Oct 17 12 PM"
+        );
+        // All-day events end the day before their DTEND
+        assert_eq!(
+            event_when(&event(at(15, 0), at(19, 0), true), now),
+            "Continuing until Sun Oct 18"
+        );
+        assert_eq!(
+            event_when(&event(at(19, 0), at(21, 0), true), now),
+            "Mon Oct 19 - Tue Oct 20"
+        );
+        assert_eq!(
+            event_when(&event(at(17, 12), at(17, 13), false), now),
+            "Sat Oct 17 12 PM - Sat Oct 17 1 PM"
+        );
+    }
+
+    /// Ink and third color pixels around the title of a single event
+    fn render_event(panel: crate::panel::Panel, allday: bool) -> (usize, usize) {
+        let now = Local::now();
+        let data = DisplayData {
+            now,
+            calendar: crate::data::calendar::Calendar {
+                events: vec![CalendarEvent {
+                    title: "Holiday".to_string(),
+                    ..event(now, now + TimeDelta::hours(1), allday)
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut display = Disp::new(panel);
+        create_image(&mut display, &data).unwrap();
+        let l = Layout::new(&display);
+        // Without alerts the first title is at the top of the column
+        let column = Rectangle::with_corners(
+            Point::new(
+                l.right_x - LINE_GAP,
+                l.top - l.fonts.body.ascent as i32 - LINE_GAP,
+            ),
+            Point::new(l.width - 1, l.top - l.fonts.body.descent as i32 + LINE_GAP),
+        );
+        let count = |color| {
+            column
+                .points()
+                .filter(|&x| display.pixel(x) == color)
+                .count()
+        };
+        (count(TriColor::White), count(TriColor::Chromatic))
+    }
+
+    #[test]
+    fn all_day_events_are_highlighted() {
+        use crate::panel::Panel;
+
+        let (_, red) = render_event(Panel::Epd7in5V3, true);
+        assert!(red > 0);
+        assert_eq!(render_event(Panel::Epd7in5V3, false).1, 0);
+
+        // Inverted, so the title's box is mostly ink
+        let (inverted, red) = render_event(Panel::Epd7in5V2, true);
+        let (plain, _) = render_event(Panel::Epd7in5V2, false);
+        assert_eq!(red, 0);
+        assert!(inverted > plain * 2, "{inverted} vs {plain}");
+    }
+}
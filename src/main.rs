@@ -1,60 +1,53 @@
-use std::{cell::RefCell, rc::Rc};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::{cell::RefCell, rc::Rc, thread};
 
-use epd_waveshare::epd7in5_v2::*;
-use epd_waveshare::prelude::WaveshareDisplay;
-use linux_embedded_hal::{
-    spidev::{self, SpidevOptions},
-    Delay, SPIError, SpidevDevice,
-};
-use rppal::gpio::{Gpio, OutputPin};
+use chrono::Local;
 use signal_hook::consts::signal::{SIGINT, SIGTERM, SIGUSR1};
 use signal_hook::iterator::Signals;
 
 use crate::{
+    backend::{backend_from_env, refresher_from_env, DisplayBackend, Refresh, Refresher},
     data::{mk_run_args, run, DisplayData, RunArgs},
     image_gen::Disp,
+    panel::panel_from_env,
+    schedule::schedule_from_env,
 };
+mod backend;
 mod data;
 mod fonts;
 mod image_gen;
-
-pub const EPD_RST_PIN: u8 = 17;
-pub const EPD_DC_PIN: u8 = 25;
-pub const EPD_CS_PIN: u8 = 8;
-pub const EPD_PWR_PIN: u8 = 18;
-pub const EPD_BUSY_PIN: u8 = 24;
-pub const EPD_MOSI_PIN: u8 = 10;
-pub const EPD_SCLK_PIN: u8 = 11;
-
-type Device<'a> =
-    Epd7in5<SpidevDevice, rppal::gpio::InputPin, &'a mut OutputPin, &'a mut OutputPin, Delay>;
+mod panel;
+mod schedule;
 
 fn run_and_update(
-    cs: &mut OutputPin,
-    pwr: &mut OutputPin,
+    backend: &mut dyn DisplayBackend,
+    refresher: &mut Refresher,
     display: &mut Disp,
-    device: &mut Device,
-    delay: &mut Delay,
-    spi: &mut SpidevDevice,
     runargs: &RunArgs,
     state: Rc<RefCell<DisplayData>>,
 ) {
-    run(display, &runargs, state);
-    eprintln!("Updating display");
-    cs.set_high();
-    pwr.set_high();
-    if let Err(e) = device.wake_up(spi, delay) {
+    let clock = run(display, runargs, state);
+    let Some(refresh) = refresher.plan(display, clock) else {
+        eprintln!("Frame unchanged, leaving the display alone");
+        return;
+    };
+    eprintln!("Updating display: {:?}", refresh);
+    if let Err(e) = backend.init() {
         eprintln!("Couldn't wake up display: {e}");
+        refresher.reset();
         return;
     };
-    if let Err(e) = device.update_and_display_frame(spi, display.buffer(), delay) {
+    let result = match refresh {
+        Refresh::Full => backend.show(display),
+        Refresh::Partial(area) => backend.show_partial(display, area),
+    };
+    if let Err(e) = result {
         eprintln!("Couldn't update display: {e}");
+        refresher.reset();
     }
-    if let Err(e) = device.sleep(spi, delay) {
+    if let Err(e) = backend.sleep() {
         eprintln!("Couldn't put display to sleep: {e}");
     }
-    cs.set_low();
-    pwr.set_low();
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -62,46 +55,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     eprintln!("Running with config: {:?}", runargs);
 
-    let mut spi = SpidevDevice::open("/dev/spidev0.0").expect("spidev directory");
-    let options = SpidevOptions::new()
-        .bits_per_word(8)
-        .max_speed_hz(4_000_000)
-        .mode(spidev::SpiModeFlags::SPI_MODE_0)
-        .build();
-    spi.configure(&options).expect("spi configuration");
-
-    eprintln!("setting up gpio");
-    let gpio = Gpio::new()?;
-
-    let busy = gpio.get(EPD_BUSY_PIN)?.into_input();
-    let mut rst = gpio.get(EPD_RST_PIN)?.into_output();
-    let mut dc = gpio.get(EPD_DC_PIN)?.into_output();
-    let mut cs = gpio.get(EPD_CS_PIN)?.into_output();
-    let mut pwr = gpio.get(EPD_PWR_PIN)?.into_output();
-
-    cs.set_high();
-    pwr.set_high();
-
-    let mut delay = Delay {};
-    let mut device = Device::new(&mut spi, busy, &mut dc, &mut rst, &mut delay, None)
-        .expect("Failed to create Epd7in5");
-    eprintln!("Created display");
-    let mut display = Display7in5::default();
-
-    cs.set_low();
-    pwr.set_low();
+    let panel = panel_from_env();
+    let mut backend = backend_from_env(panel)?;
+    let mut display = Disp::new(panel);
+    let mut refresher = refresher_from_env();
+    // Without partial refreshes every minute would flash the whole panel
+    let schedule = schedule_from_env(backend.supports_partial() && refresher.full_every > 0);
 
     eprintln!("Device successfully initialized!");
 
     eprintln!("Starting initial update");
-    let  state = Rc::new(RefCell::new(DisplayData::default()));
+    let state = Rc::new(RefCell::new(DisplayData::load(&runargs)));
     run_and_update(
-        &mut cs,
-        &mut pwr,
+        backend.as_mut(),
+        &mut refresher,
         &mut display,
-        &mut device,
-        &mut delay,
-        &mut spi,
         &runargs,
         state.clone(),
     );
@@ -111,23 +79,59 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     eprintln!("Waiting for signals...");
     eprintln!("SIGUSR1 ? update display");
     eprintln!("SIGINT/SIGTERM ? exit");
+    if schedule.interval > 0 {
+        eprintln!("Updating every {} seconds", schedule.interval);
+    }
+    if schedule.clock {
+        eprintln!("Updating the clock every minute");
+    }
+
+    // Signals arrive over a channel so the wait can time out when an update
+    // is due
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            if sender.send(signal).is_err() {
+                break;
+            }
+        }
+    });
 
-    for signal in signals.forever() {
+    loop {
+        let signal = if let Some(timeout) = schedule.until_next(Local::now()) {
+            match receiver.recv_timeout(timeout) {
+                Ok(signal) => Some(signal),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(signal) => Some(signal),
+                Err(_) => break,
+            }
+        };
         match signal {
-            SIGUSR1 => {
+            None => {
+                eprintln!("Running scheduled update");
+                run_and_update(
+                    backend.as_mut(),
+                    &mut refresher,
+                    &mut display,
+                    &runargs,
+                    state.clone(),
+                );
+            }
+            Some(SIGUSR1) => {
                 println!("SIGUSR1 received: running update");
                 run_and_update(
-                    &mut cs,
-                    &mut pwr,
+                    backend.as_mut(),
+                    &mut refresher,
                     &mut display,
-                    &mut device,
-                    &mut delay,
-                    &mut spi,
                     &runargs,
                     state.clone(),
                 );
             }
-            SIGINT | SIGTERM => {
+            Some(SIGINT | SIGTERM) => {
                 println!("Exit signal received");
                 break;
             }
@@ -136,54 +140,127 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
     Ok(())
 }
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use image::{ImageBuffer, Luma};
-
-    use image_gen::{HEIGHT, WIDTH};
+    use embedded_graphics::prelude::*;
+    use epd_waveshare::color::TriColor;
 
-    #[test]
-    fn render_to_png() -> Result<(), Box<dyn std::error::Error>> {
-        let runargs = data::RunArgs {
+    /// Arguments reading the NWS fixtures from a mock server and the test calendar
+    fn fixture_args() -> data::RunArgs {
+        let base_url = data::mock::serve(vec![
+            (
+                "/points/42.3297,-83.0425",
+                200,
+                data::mock::fixture("nws_points.json"),
+            ),
+            (
+                "/gridpoints/DTX/66,34/forecast",
+                200,
+                data::mock::fixture("nws_forecast.json"),
+            ),
+            (
+                "/gridpoints/DTX/66,34/forecast/hourly",
+                200,
+                data::mock::fixture("nws_hourly.json"),
+            ),
+            (
+                "/alerts/active",
+                200,
+                data::mock::fixture("nws_alerts.json"),
+            ),
+            (
+                "/gridpoints/DTX/66,34/stations",
+                200,
+                data::mock::fixture("nws_stations.json"),
+            ),
+            (
+                "/stations/KDET/observations/latest",
+                200,
+                data::mock::fixture("nws_observation.json"),
+            ),
+        ]);
+        data::RunArgs {
             lat: 42.3297,
             lon: -83.0425,
-            ics: "./test/test.ics".to_string(),
+            weather: Box::new(data::weather::Nws::new(base_url, None)),
+            sources: vec![data::calendar::CalendarSource {
+                location: "./test/test.ics".to_string(),
+                label: None,
+                http: Default::default(),
+            }],
             max_events: 10,
+            horizon_days: 60,
             weather_ttl: 0,
             calendar_ttl: 0,
-        };
+            stale_after: 3 * 3600,
+            state_dir: None,
+            units: Default::default(),
+        }
+    }
+
+    #[test]
+    fn render_to_png() -> Result<(), Box<dyn std::error::Error>> {
+        let runargs = fixture_args();
         eprintln!("Test render with config: {:?}", runargs);
 
-        let mut display = Display7in5::default();
+        let mut display = Disp::new(panel::Panel::default());
 
-        let data = data::run(&mut display, &runargs, None);
+        let data = Rc::new(RefCell::new(DisplayData::default()));
+        data::run(&mut display, &runargs, data.clone());
 
         println!("Data: {:?}", data);
 
-        let mut img: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::new(WIDTH as u32, HEIGHT as u32);
-
-        let buffer = display.buffer();
-
-        let bytes_per_row = (WIDTH as usize + 7) / 8;
+        let mut backend = backend::PngFile {
+            path: "./test/test_output.png".into(),
+        };
+        backend.init()?;
+        backend.show(&display)?;
+        backend.sleep()?;
 
-        for y in 0..HEIGHT as usize {
-            for x in 0..WIDTH as usize {
-                let byte_idx = y * bytes_per_row + (x / 8);
-                let bit = 7 - (x % 8);
+        Ok(())
+    }
 
-                let value = if (buffer[byte_idx] >> bit) & 1 == 0 {
-                    255
-                } else {
-                    0
-                };
+    /// The smaller layouts, checked for panics, for anything drawn over the
+    /// clock and for a clock that fits the time
+    #[test]
+    fn render_small_panels() {
+        let runargs = fixture_args();
+        let data = Rc::new(RefCell::new(DisplayData::default()));
+        for panel in [panel::Panel::Epd4in2, panel::Panel::Epd2in9V2] {
+            let mut display = Disp::new(panel);
+            let clock = data::run(&mut display, &runargs, data.clone()).unwrap();
+            assert!(
+                clock.intersection(&display.bounding_box()) == clock,
+                "{panel:?} clock {clock:?} is off the panel"
+            );
 
-                img.put_pixel(x as u32, y as u32, Luma([value]));
-            }
+            let with_time = display.clone();
+            let mut data = data.borrow_mut();
+            data.time = String::new();
+            image_gen::create_image(&mut display, &data).unwrap();
+            let covered = clock
+                .points()
+                .filter(|&x| display.pixel(x) != TriColor::Black)
+                .count();
+            assert_eq!(covered, 0, "{panel:?} draws over the clock");
+            // ...and the clock covers the time it shows and not much more
+            let time: Vec<Point> = display
+                .bounding_box()
+                .points()
+                .filter(|&x| display.pixel(x) != with_time.pixel(x))
+                .collect();
+            assert!(
+                time.iter().all(|&x| clock.contains(x)),
+                "{panel:?} time is drawn outside the clock {clock:?}"
+            );
+            let time_right = time.iter().map(|x| x.x).max().unwrap();
+            let clock_right = clock.top_left.x + clock.size.width as i32;
+            assert!(
+                clock_right - time_right < 12,
+                "{panel:?} clock {clock:?} is wider than the time, which ends at {time_right}"
+            );
         }
-
-        img.save("./test/test_output.png")?;
-
-        Ok(())
     }
 }
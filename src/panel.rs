@@ -0,0 +1,349 @@
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use epd_waveshare::color::TriColor;
+use std::env::{self, VarError};
+use std::str::FromStr;
+
+/// The Waveshare panels there are drivers for. Layouts are always drawn in
+/// landscape, portrait panels get the frame turned on its side.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Panel {
+    /// 7.5" v1, 640x384
+    Epd7in5,
+    /// 7.5" v2, 800x480
+    #[default]
+    Epd7in5V2,
+    /// 7.5" v3, 800x480. Sold as the black/white/red (B) panel.
+    Epd7in5V3,
+    /// 7.5" HD, 880x528
+    Epd7in5Hd,
+    /// 5.83" v2, 648x480
+    Epd5in83V2,
+    /// 4.2", 400x300
+    Epd4in2,
+    /// 2.9" v2, 128x296
+    Epd2in9V2,
+}
+
+impl Panel {
+    /// Width and height as the driver expects the buffer
+    pub fn native_size(self) -> Size {
+        match self {
+            Self::Epd7in5 => Size::new(640, 384),
+            Self::Epd7in5V2 | Self::Epd7in5V3 => Size::new(800, 480),
+            Self::Epd7in5Hd => Size::new(880, 528),
+            Self::Epd5in83V2 => Size::new(648, 480),
+            Self::Epd4in2 => Size::new(400, 300),
+            Self::Epd2in9V2 => Size::new(128, 296),
+        }
+    }
+
+    /// Width and height as the layout sees them, always landscape
+    pub fn size(self) -> Size {
+        let size = self.native_size();
+        if self.is_portrait() {
+            Size::new(size.height, size.width)
+        } else {
+            size
+        }
+    }
+
+    fn is_portrait(self) -> bool {
+        let size = self.native_size();
+        size.width < size.height
+    }
+
+    /// Whether the panel has a third color, red on the 7.5" v3
+    pub fn has_color(self) -> bool {
+        self == Self::Epd7in5V3
+    }
+
+    /// Whether a set bit shows white on this panel, so the frame has to be
+    /// inverted on its way out. Matches Waveshare's reference code.
+    fn set_is_white(self) -> bool {
+        !matches!(self, Self::Epd7in5V2 | Self::Epd5in83V2)
+    }
+}
+
+/// `7in5`, `7in5_v2`, `7in5_v3`, `7in5_hd`, `5in83_v2`, `4in2` or `2in9_v2`,
+/// as the `epd-waveshare` modules are named
+impl FromStr for Panel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().trim_start_matches("epd").to_lowercase().as_str() {
+            "7in5" => Ok(Self::Epd7in5),
+            "7in5_v2" => Ok(Self::Epd7in5V2),
+            "7in5_v3" | "7in5b_v3" | "7in5b_v2" => Ok(Self::Epd7in5V3),
+            "7in5_hd" => Ok(Self::Epd7in5Hd),
+            "5in83_v2" => Ok(Self::Epd5in83V2),
+            "4in2" => Ok(Self::Epd4in2),
+            "2in9_v2" => Ok(Self::Epd2in9V2),
+            _ => Err(format!("Unknown panel `{}`", s)),
+        }
+    }
+}
+
+pub fn panel_from_env() -> Panel {
+    match env::var("PANEL") {
+        Ok(s) => s.parse().expect(
+            "PANEL must be one of 7in5, 7in5_v2, 7in5_v3, 7in5_hd, 5in83_v2, 4in2, 2in9_v2",
+        ),
+        Err(VarError::NotPresent) => Panel::default(),
+        Err(VarError::NotUnicode(_)) => panic!("PANEL must be unicode"),
+    }
+}
+
+/// A frame for a [`Panel`]. [`TriColor::White`] is ink and sets a bit, like
+/// the `epd-waveshare` displays, the background is [`TriColor::Black`] and
+/// [`TriColor::Chromatic`] is the panel's third color. Panels without one show
+/// it as ink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    panel: Panel,
+    /// Rows of the panel's native orientation, most significant bit first
+    buffer: Vec<u8>,
+    /// Same layout as `buffer`, for panels with a third color
+    chromatic: Option<Vec<u8>>,
+}
+
+impl Frame {
+    pub fn new(panel: Panel) -> Self {
+        let size = panel.native_size();
+        let buffer = vec![0; size.width.div_ceil(8) as usize * size.height as usize];
+        Frame {
+            panel,
+            chromatic: panel.has_color().then(|| buffer.clone()),
+            buffer,
+        }
+    }
+
+    pub fn has_color(&self) -> bool {
+        self.chromatic.is_some()
+    }
+
+    /// The buffer to send to the panel's driver, followed by the chromatic
+    /// one if there is one
+    pub fn to_panel(&self) -> Vec<u8> {
+        let mut buffer = if self.panel.set_is_white() {
+            self.buffer.iter().map(|x| !x).collect()
+        } else {
+            self.buffer.clone()
+        };
+        if let Some(chromatic) = &self.chromatic {
+            buffer.extend_from_slice(chromatic);
+        }
+        buffer
+    }
+
+    /// The byte index and mask of a landscape point, `None` if off the panel
+    fn position(&self, point: Point) -> Option<(usize, u8)> {
+        let size = self.panel.size();
+        if point.x < 0
+            || point.y < 0
+            || point.x >= size.width as i32
+            || point.y >= size.height as i32
+        {
+            return None;
+        }
+        let (x, y) = (point.x as u32, point.y as u32);
+        let native = self.panel.native_size();
+        // Turned clockwise, so the top of the layout is the panel's right edge
+        let (x, y) = if self.panel.is_portrait() {
+            (native.width - 1 - y, x)
+        } else {
+            (x, y)
+        };
+        let index = (y * native.width.div_ceil(8) + x / 8) as usize;
+        Some((index, 0x80 >> (x % 8)))
+    }
+
+    /// A landscape `area` in the panel's native orientation, cut to the panel
+    pub fn to_native(&self, area: Rectangle) -> Rectangle {
+        let area = area.intersection(&self.bounding_box());
+        if !self.panel.is_portrait() || area.is_zero_sized() {
+            return area;
+        }
+        let native = self.panel.native_size();
+        Rectangle::new(
+            Point::new(
+                native.width as i32 - area.top_left.y - area.size.height as i32,
+                area.top_left.x,
+            ),
+            Size::new(area.size.height, area.size.width),
+        )
+    }
+
+    /// The smallest landscape area holding every pixel that differs from
+    /// `other`, `None` if they are the same. All of it for another panel.
+    pub fn diff(&self, other: &Frame) -> Option<Rectangle> {
+        if self == other {
+            return None;
+        }
+        if self.panel != other.panel {
+            return Some(self.bounding_box());
+        }
+        let (mut min, mut max) = (Point::new(i32::MAX, i32::MAX), Point::zero());
+        for point in self
+            .bounding_box()
+            .points()
+            .filter(|&point| self.pixel(point) != other.pixel(point))
+        {
+            min = min.component_min(point);
+            max = max.component_max(point);
+        }
+        Some(Rectangle::with_corners(min, max))
+    }
+
+    /// The color at a landscape `point`, the background if off the panel
+    pub fn pixel(&self, point: Point) -> TriColor {
+        let Some((index, mask)) = self.position(point) else {
+            return TriColor::Black;
+        };
+        if self
+            .chromatic
+            .as_ref()
+            .is_some_and(|x| x[index] & mask != 0)
+        {
+            TriColor::Chromatic
+        } else if self.buffer[index] & mask != 0 {
+            TriColor::White
+        } else {
+            TriColor::Black
+        }
+    }
+}
+
+impl DrawTarget for Frame {
+    type Color = TriColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let Some((index, mask)) = self.position(point) else {
+                continue;
+            };
+            let (ink, chromatic) = match (color, &self.chromatic) {
+                (TriColor::White, _) => (true, false),
+                (TriColor::Black, _) => (false, false),
+                (TriColor::Chromatic, Some(_)) => (false, true),
+                (TriColor::Chromatic, None) => (true, false),
+            };
+            if ink {
+                self.buffer[index] |= mask;
+            } else {
+                self.buffer[index] &= !mask;
+            }
+            if let Some(plane) = &mut self.chromatic {
+                if chromatic {
+                    plane[index] |= mask;
+                } else {
+                    plane[index] &= !mask;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Frame {
+    fn size(&self) -> Size {
+        self.panel.size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn portrait_panels_turn_the_frame() {
+        let mut frame = Frame::new(Panel::Epd2in9V2);
+        assert_eq!(frame.size(), Size::new(296, 128));
+        assert_eq!(frame.buffer.len(), 16 * 296);
+
+        Pixel(Point::new(0, 0), TriColor::White)
+            .draw(&mut frame)
+            .unwrap();
+        Pixel(Point::new(295, 127), TriColor::White)
+            .draw(&mut frame)
+            .unwrap();
+        // Off the panel
+        Pixel(Point::new(296, 0), TriColor::White)
+            .draw(&mut frame)
+            .unwrap();
+        assert_eq!(frame.pixel(Point::new(0, 0)), TriColor::White);
+        assert_eq!(frame.pixel(Point::new(295, 127)), TriColor::White);
+        // Top left is the end of the first native row, bottom right the
+        // start of the last
+        assert_eq!(frame.buffer[15], 0x01);
+        assert_eq!(frame.buffer[16 * 295], 0x80);
+        assert_eq!(frame.buffer.iter().map(|x| x.count_ones()).sum::<u32>(), 2);
+
+        // This panel shows set bits as white
+        assert_eq!(frame.to_panel()[15], 0xFE);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!("7in5_v2".parse(), Ok(Panel::Epd7in5V2));
+        assert_eq!("epd4in2".parse(), Ok(Panel::Epd4in2));
+        assert!("13in3".parse::<Panel>().is_err());
+    }
+
+    #[test]
+    fn third_color() {
+        let mut frame = Frame::new(Panel::Epd7in5V3);
+        let point = Point::new(9, 0);
+        Pixel(point, TriColor::White).draw(&mut frame).unwrap();
+        Pixel(point, TriColor::Chromatic).draw(&mut frame).unwrap();
+        assert_eq!(frame.pixel(point), TriColor::Chromatic);
+        // Inverted black and white, then red
+        let buffer = frame.to_panel();
+        assert_eq!(buffer.len(), 2 * 100 * 480);
+        assert_eq!((buffer[1], buffer[100 * 480 + 1]), (0xFF, 0x40));
+
+        Pixel(point, TriColor::White).draw(&mut frame).unwrap();
+        assert_eq!(frame.pixel(point), TriColor::White);
+
+        // Ink where there is no third color
+        let mut frame = Frame::new(Panel::Epd7in5V2);
+        Pixel(point, TriColor::Chromatic).draw(&mut frame).unwrap();
+        assert_eq!(frame.pixel(point), TriColor::White);
+        assert!(!frame.has_color());
+    }
+
+    #[test]
+    fn areas() {
+        let frame = Frame::new(Panel::Epd2in9V2);
+        let area = Rectangle::new(Point::new(10, 20), Size::new(30, 40));
+        assert_eq!(
+            frame.to_native(area),
+            Rectangle::new(Point::new(68, 10), Size::new(40, 30))
+        );
+
+        let mut other = frame.clone();
+        assert_eq!(frame.diff(&other), None);
+        Pixel(Point::new(15, 25), TriColor::White)
+            .draw(&mut other)
+            .unwrap();
+        assert_eq!(
+            frame.diff(&other),
+            Some(Rectangle::new(Point::new(15, 25), Size::new(1, 1)))
+        );
+        Pixel(Point::new(40, 5), TriColor::White)
+            .draw(&mut other)
+            .unwrap();
+        assert_eq!(
+            frame.diff(&other),
+            Some(Rectangle::new(Point::new(15, 5), Size::new(26, 21)))
+        );
+        assert_eq!(
+            frame.diff(&Frame::new(Panel::Epd4in2)),
+            Some(frame.bounding_box())
+        );
+    }
+}
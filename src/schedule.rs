@@ -0,0 +1,86 @@
+use chrono::{DateTime, Local};
+use std::env::{self, VarError};
+use std::time::Duration;
+
+/// When the daemon wakes up by itself to update the display. Weather and
+/// calendars are only fetched again once their TTLs are up, so waking more
+/// often than that only redraws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    /// Seconds between updates, counted from midnight so 300 updates at :00,
+    /// :05 and so on. 0 leaves updates to SIGUSR1.
+    pub interval: u64,
+    /// Whether to also update at the start of every minute for the clock
+    pub clock: bool,
+}
+
+impl Schedule {
+    /// How long after `now` the next update is due, `None` if never
+    pub fn until_next(&self, now: DateTime<Local>) -> Option<Duration> {
+        // Local milliseconds, so the boundaries follow the clock on the wall
+        let millis = now.naive_local().and_utc().timestamp_millis();
+        let until = |step: i64| step - millis.rem_euclid(step);
+        let interval = (self.interval > 0).then(|| until(self.interval as i64 * 1000));
+        let minute = self.clock.then(|| until(60_000));
+        let next = match (interval, minute) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b)?,
+        };
+        Some(Duration::from_millis(next as u64))
+    }
+}
+
+/// Updates every `UPDATE_INTERVAL` seconds, 300 unless set, and every minute
+/// as well if `clock`
+pub fn schedule_from_env(clock: bool) -> Schedule {
+    let interval = match env::var("UPDATE_INTERVAL") {
+        Ok(s) => s
+            .parse()
+            .expect("UPDATE_INTERVAL must be a non-negative integer"),
+        Err(VarError::NotPresent) => 300,
+        Err(VarError::NotUnicode(_)) => panic!("UPDATE_INTERVAL must be unicode"),
+    };
+    Schedule { interval, clock }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn aligned_to_the_clock() {
+        let now = Local.with_ymd_and_hms(2024, 3, 1, 10, 3, 20).unwrap()
+            + chrono::TimeDelta::milliseconds(500);
+        let schedule = Schedule {
+            interval: 300,
+            clock: false,
+        };
+        assert_eq!(
+            schedule.until_next(now),
+            Some(Duration::from_millis(99_500))
+        );
+        let schedule = Schedule {
+            interval: 300,
+            clock: true,
+        };
+        assert_eq!(
+            schedule.until_next(now),
+            Some(Duration::from_millis(39_500))
+        );
+        let schedule = Schedule {
+            interval: 0,
+            clock: false,
+        };
+        assert_eq!(schedule.until_next(now), None);
+        // On a boundary the next one is a whole step away
+        let schedule = Schedule {
+            interval: 60,
+            clock: false,
+        };
+        assert_eq!(
+            schedule.until_next(Local.with_ymd_and_hms(2024, 3, 1, 10, 3, 0).unwrap()),
+            Some(Duration::from_secs(60))
+        );
+    }
+}